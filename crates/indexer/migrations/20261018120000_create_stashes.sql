@@ -0,0 +1,32 @@
+-- The latest state of every public stash, upserted by its id
+CREATE TABLE IF NOT EXISTS stashes (
+    id TEXT PRIMARY KEY,
+    public BOOLEAN NOT NULL,
+    account_name TEXT,
+    stash TEXT,
+    stash_type TEXT NOT NULL,
+    league TEXT,
+    realm TEXT NOT NULL,
+    change_id TEXT NOT NULL,
+    next_change_id TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS stashes_league_idx ON stashes (realm, league);
+CREATE INDEX IF NOT EXISTS stashes_account_name_idx ON stashes (account_name);
+
+-- Items are replaced as a whole whenever their stash is updated
+CREATE TABLE IF NOT EXISTS items (
+    stash_id TEXT NOT NULL REFERENCES stashes (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    id TEXT,
+    name TEXT NOT NULL,
+    type_line TEXT NOT NULL,
+    base_type TEXT NOT NULL,
+    note TEXT,
+    data JSONB NOT NULL,
+    PRIMARY KEY (stash_id, position)
+);
+
+CREATE INDEX IF NOT EXISTS items_id_idx ON items (id);
+CREATE INDEX IF NOT EXISTS items_base_type_idx ON items (base_type);
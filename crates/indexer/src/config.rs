@@ -1,6 +1,12 @@
+use std::collections::HashMap;
+
+use stash_api::common::{realm::Realm, schema::OutputSchema};
 use trade_common::secret::SecretString;
 
-use crate::sinks::{rabbitmq::RabbitMqConfig, s3::S3Config};
+use crate::sinks::{
+    file::FileConfig, kafka::KafkaConfig, postgres::PostgresConfig, rabbitmq::RabbitMqConfig,
+    s3::S3Config, sink::SinkPayload,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RestartMode {
@@ -23,12 +29,26 @@ impl RestartMode {
 pub struct Configuration {
     pub rabbitmq: Option<RabbitMqConfig>,
     pub s3: Option<S3Config>,
-    // pub postgres: Option<PostgresConfig>,
+    pub postgres: Option<PostgresConfig>,
+    pub file: Option<FileConfig>,
+    pub kafka: Option<KafkaConfig>,
     pub metrics_port: u32,
     pub client_id: String,
     pub client_secret: SecretString,
     pub developer_mail: SecretString,
     pub restart_mode: RestartMode,
+    pub stash_api_base_url: Option<String>,
+    pub oauth_base_url: Option<String>,
+    pub poe_ninja_base_url: Option<String>,
+    pub buffer_size: Option<u32>,
+    pub record_dir: Option<String>,
+    pub decode_retries: Option<u32>,
+    pub dead_letter_dir: Option<String>,
+    pub realms: Vec<Realm>,
+    /// Where to start realms that poe.ninja does not track, in [`RestartMode::Fresh`]
+    pub start_change_ids: HashMap<Realm, String>,
+    pub lag_check_interval_secs: u32,
+    pub lag_alert_threshold_secs: u32,
 }
 
 impl Configuration {
@@ -37,11 +57,37 @@ impl Configuration {
             metrics_port: read_int_from_env("METRICS_PORT").unwrap_or(4000),
             rabbitmq: RabbitMqConfig::from_env()?,
             s3: S3Config::from_env()?,
-            // postgres: PostgresConfig::from_env()?,
+            postgres: PostgresConfig::from_env()?,
+            file: FileConfig::from_env()?,
+            kafka: KafkaConfig::from_env()?,
             client_id: ensure_string_from_env("POE_CLIENT_ID"),
             client_secret: SecretString::new(ensure_string_from_env("POE_CLIENT_SECRET")),
             developer_mail: SecretString::new(ensure_string_from_env("POE_DEVELOPER_MAIL")),
             restart_mode: RestartMode::from_env(),
+            stash_api_base_url: read_string_from_env("POE_STASH_API_BASE_URL"),
+            oauth_base_url: read_string_from_env("POE_OAUTH_BASE_URL"),
+            poe_ninja_base_url: read_string_from_env("POE_NINJA_BASE_URL"),
+            buffer_size: read_int_from_env("BUFFER_SIZE"),
+            record_dir: read_string_from_env("RECORD_DIR"),
+            decode_retries: read_int_from_env("DECODE_RETRIES"),
+            dead_letter_dir: read_string_from_env("DEAD_LETTER_DIR"),
+            realms: read_string_from_env("REALMS")
+                .map(|s| {
+                    s.split(',')
+                        .map(|realm| realm.trim().parse())
+                        .collect::<Result<Vec<_>, _>>()
+                        .unwrap_or_else(|e| panic!("Invalid REALMS: {e}"))
+                })
+                .unwrap_or_else(|| vec![Realm::Pc]),
+            start_change_ids: Realm::ALL
+                .into_iter()
+                .filter_map(|realm| {
+                    let name = format!("START_CHANGE_ID_{}", realm.to_string().to_uppercase());
+                    read_string_from_env(&name).map(|change_id| (realm, change_id))
+                })
+                .collect(),
+            lag_check_interval_secs: read_int_from_env("LAG_CHECK_INTERVAL_SECS").unwrap_or(60),
+            lag_alert_threshold_secs: read_int_from_env("LAG_ALERT_THRESHOLD_SECS").unwrap_or(300),
         })
     }
 }
@@ -59,6 +105,29 @@ fn ensure_int_from_env(name: &str) -> u32 {
     ensure_string_from_env(name).parse().unwrap()
 }
 
+/// Reads the [`OutputSchema`] of a sink, defaults to snake_case
+pub fn read_schema_from_env(name: &str) -> OutputSchema {
+    read_string_from_env(name)
+        .map(|s| s.parse().unwrap_or_else(|e| panic!("Invalid {name}: {e}")))
+        .unwrap_or_default()
+}
+
+/// Reads the [`SinkPayload`] of a sink, defaults to [`SinkPayload::Stashes`]
+pub fn read_payload_from_env(name: &str) -> SinkPayload {
+    read_string_from_env(name)
+        .map(|s| s.parse().unwrap_or_else(|e| panic!("Invalid {name}: {e}")))
+        .unwrap_or_default()
+}
+
+/// Fails on [`SinkPayload::Items`] for sinks that only handle [`Stash`]es
+///
+/// [`Stash`]: stash_api::common::stash::Stash
+pub fn ensure_stashes_payload_from_env(name: &str) {
+    if read_payload_from_env(name) == SinkPayload::Items {
+        panic!("Invalid {name}: this sink does not support item records");
+    }
+}
+
 pub fn read_int_from_env(name: &str) -> Option<u32> {
     std::env::var(name).map(|s| s.parse::<u32>().unwrap()).ok()
 }
@@ -0,0 +1,172 @@
+use std::time::{Duration, Instant};
+
+use prometheus_exporter::prometheus::IntGauge;
+use stash_api::common::{poe_ninja_client::PoeNinjaClient, ChangeId};
+use tokio::sync::watch;
+
+/// Periodically compares the change id the indexer is at with the latest change id that
+/// poe.ninja knows about.
+pub struct LagMonitor {
+    pub poe_ninja: PoeNinjaClient,
+    pub interval: Duration,
+    /// Log an alert once the estimated lag exceeds this
+    pub alert_threshold: Duration,
+    pub lag_changes: IntGauge,
+    pub lag_seconds: IntGauge,
+}
+
+impl LagMonitor {
+    /// Runs until `progress` is closed, which holds the change id the indexer is at.
+    pub async fn run(self, mut progress: watch::Receiver<Option<ChangeId>>) {
+        let mut interval = tokio::time::interval(self.interval);
+        let mut previous_head: Option<(ChangeId, Instant)> = None;
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                changed = progress.changed() => match changed {
+                    Ok(_) => continue,
+                    Err(_) => return,
+                },
+            }
+
+            let head = match self.poe_ninja.fetch_latest_change_id().await {
+                Ok(head) => head,
+                Err(e) => {
+                    tracing::warn!("Failed fetching latest change id for lag estimation: {}", e);
+                    continue;
+                }
+            };
+            let now = Instant::now();
+
+            let rate = previous_head
+                .as_ref()
+                .and_then(|(previous, at)| rate(previous, &head, now.duration_since(*at)));
+            previous_head = Some((head.clone(), now));
+
+            let Some(current) = progress.borrow().clone() else {
+                continue;
+            };
+            let Some(lag) = estimate(&current, &head, rate) else {
+                tracing::warn!(
+                    "Cannot compare change id {} with latest change id {}",
+                    current,
+                    head
+                );
+                continue;
+            };
+
+            self.lag_changes.set(lag.changes as i64);
+            tracing::debug!("Indexer is {} changes behind {}", lag.changes, head);
+
+            // Don't keep exporting an estimate of an earlier check
+            let seconds = lag.time.map_or(0, |time| time.as_secs());
+            self.lag_seconds.set(seconds as i64);
+
+            if lag.exceeds(self.alert_threshold) {
+                tracing::warn!(
+                    "Indexer is lagging behind by about {}s ({} changes), exceeding the threshold of {}s",
+                    seconds,
+                    lag.changes,
+                    self.alert_threshold.as_secs()
+                );
+            }
+        }
+    }
+}
+
+/// How far the indexer is behind the latest change id
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lag {
+    /// Shard increments, see [`ChangeId::lag`]
+    pub changes: u64,
+    /// How long the river takes to advance that much, unknown until its rate was measured
+    pub time: Option<Duration>,
+}
+
+impl Lag {
+    pub fn exceeds(&self, threshold: Duration) -> bool {
+        self.time.is_some_and(|time| time > threshold)
+    }
+}
+
+/// How many changes the river advances per second, based on two heads fetched `elapsed` apart
+fn rate(previous: &ChangeId, head: &ChangeId, elapsed: Duration) -> Option<f64> {
+    let rate = previous.lag(head)? as f64 / elapsed.as_secs_f64();
+    (rate.is_finite() && rate > 0.0).then_some(rate)
+}
+
+/// How far `current` is behind `head`, given the `rate` of the river in changes per second
+fn estimate(current: &ChangeId, head: &ChangeId, rate: Option<f64>) -> Option<Lag> {
+    let changes = current.lag(head)?;
+    Some(Lag {
+        changes,
+        time: rate.map(|rate| Duration::from_secs_f64(changes as f64 / rate)),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use stash_api::common::ChangeId;
+
+    use super::{estimate, rate, Lag};
+
+    fn change_id(s: &str) -> ChangeId {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_rate() {
+        let previous = change_id("10-20-30");
+        let head = change_id("15-25-40");
+        assert_eq!(rate(&previous, &head, Duration::from_secs(10)), Some(2.0));
+        // The river did not move or the heads cannot be compared
+        assert_eq!(rate(&head, &head, Duration::from_secs(10)), None);
+        assert_eq!(rate(&previous, &head, Duration::ZERO), None);
+        assert_eq!(
+            rate(&change_id("1-2"), &head, Duration::from_secs(10)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_estimate() {
+        let head = change_id("15-25-40");
+        // Shards where the indexer is ahead of the head don't count
+        let current = change_id("10-30-38");
+
+        assert_eq!(
+            estimate(&current, &head, Some(2.0)),
+            Some(Lag {
+                changes: 7,
+                time: Some(Duration::from_secs_f64(3.5)),
+            })
+        );
+        assert_eq!(estimate(&head, &head, Some(2.0)).unwrap().changes, 0);
+        assert_eq!(estimate(&change_id("1-2"), &head, Some(2.0)), None);
+    }
+
+    #[test]
+    fn test_estimate_without_rate() {
+        let lag = estimate(&change_id("10-20-30"), &change_id("15-25-40"), None).unwrap();
+        assert_eq!(lag.changes, 20);
+        assert_eq!(lag.time, None);
+        assert!(!lag.exceeds(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_alert_threshold() {
+        let current = change_id("0-0");
+        let head = change_id("300-300");
+        let threshold = Duration::from_secs(300);
+
+        assert!(!estimate(&current, &head, Some(2.0))
+            .unwrap()
+            .exceeds(threshold));
+        assert!(estimate(&current, &head, Some(1.9))
+            .unwrap()
+            .exceeds(threshold));
+    }
+}
@@ -1,29 +1,47 @@
 mod config;
+mod lag;
 mod metrics;
 mod resumption;
 mod sinks;
+mod transform;
 
 extern crate dotenv;
 
 use std::{
+    collections::HashMap,
+    path::PathBuf,
     str::FromStr,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::Duration,
 };
 
-use crate::metrics::setup_metrics;
+use crate::lag::LagMonitor;
+use crate::metrics::{setup_metrics, Metrics};
 use crate::resumption::StateWrapper;
-use crate::{resumption::State, sinks::sink::setup_sinks};
+use crate::{
+    resumption::State,
+    sinks::sink::{setup_sinks, SinkPayload},
+    transform::ItemTransform,
+};
 
 use config::{Configuration, RestartMode};
 use stash_api::{
-    common::{poe_ninja_client::PoeNinjaClient, ChangeId},
-    r#async::indexer::{Indexer, IndexerMessage},
+    common::{
+        poe_ninja_client::{PoeNinjaClient, DEFAULT_POE_NINJA_BASE_URL},
+        realm::Realm,
+        ChangeId, StashApiError,
+    },
+    r#async::indexer::{Indexer, IndexerHandle, IndexerMessage},
+};
+use tokio::sync::{
+    mpsc::{channel, Receiver, Sender},
+    watch,
 };
 use tracing::info;
-use trade_common::telemetry::setup_telemetry;
+use trade_common::telemetry::{generate_http_client, setup_telemetry};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -37,50 +55,95 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let signal_flag = setup_signal_handlers()?;
     let metrics = setup_metrics(config.metrics_port)?;
     let mut sinks = setup_sinks(config.clone()).await?;
+    let transform = ItemTransform::default();
 
-    let mut resumption = StateWrapper::load_from_file(&"./indexer_state.json");
-    let indexer = Indexer::new(
-        config.client_id.clone(),
-        config.client_secret.clone(),
-        config.developer_mail.clone(),
-    );
-    let mut rx = match (&config.restart_mode, &resumption.inner) {
-        (RestartMode::Fresh, _) => {
-            let latest_change_id = PoeNinjaClient::fetch_latest_change_id_async().await?;
-            indexer.start_at_change_id(latest_change_id)
-        }
-        (RestartMode::Resume, Some(next)) => {
-            indexer.start_at_change_id(ChangeId::from_str(&next.next_change_id).unwrap())
-        }
-        (RestartMode::Resume, None) => {
-            tracing::info!("No previous data found, falling back to RestartMode::Fresh");
-            let latest_change_id = PoeNinjaClient::fetch_latest_change_id_async().await?;
-            indexer.start_at_change_id(latest_change_id)
-        }
+    // All realms share the sinks, so their messages are merged into a single channel. Pages
+    // are buffered by the indexers, so it only holds the message each realm hands over.
+    let (tx, mut rx) = channel(config.realms.len().max(1));
+    let paths = config
+        .realms
+        .iter()
+        .map(|realm| resumption_path(*realm))
+        .collect::<Vec<_>>();
+    let mut resumptions = HashMap::new();
+    let mut handles = vec![];
+    for (realm, path) in config.realms.iter().zip(&paths) {
+        let resumption = StateWrapper::load_from_file(path);
+        let (handle, realm_rx) = start_indexer(&config, *realm, &resumption).await?;
+        tokio::spawn(forward(*realm, realm_rx, tx.clone()));
+        handles.push(handle);
+        resumptions.insert(*realm, resumption);
     }
-    .await;
+    drop(tx);
 
-    while let Some(msg) = rx.recv().await {
-        if signal_flag.load(Ordering::Relaxed) {
-            tracing::info!(
-                "Shutdown signal detected. Shutting down gracefully while flushing sinks."
-            );
+    // poe.ninja only tracks the PC realm
+    let (progress, progress_rx) = watch::channel(None);
+    if config.realms.contains(&Realm::Pc) {
+        tokio::spawn(setup_lag_monitor(&config, &metrics).run(progress_rx));
+    }
+
+    let mut fatal_error = None;
+    let mut running = handles.len();
+    let mut stopping = false;
+    let mut signal_check = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        let msg = tokio::select! {
+            msg = rx.recv() => msg,
+            _ = signal_check.tick(), if !stopping => {
+                if signal_flag.load(Ordering::Relaxed) {
+                    tracing::info!(
+                        "Shutdown signal detected. Draining in-flight chunks before flushing sinks."
+                    );
+                    handles.iter().for_each(IndexerHandle::stop);
+                    stopping = true;
+                }
+                continue;
+            }
+        };
+        let Some((realm, msg)) = msg else {
             break;
-        }
+        };
+        let queue_depth = handles.iter().map(IndexerHandle::buffered).sum::<usize>() + rx.len();
+        metrics.queue_depth.set(queue_depth as i64);
 
         match msg {
-            IndexerMessage::Stop => break,
+            IndexerMessage::Stop => {
+                tracing::info!("Stopped indexing {}", realm);
+                running -= 1;
+                if running == 0 {
+                    break;
+                }
+            }
             IndexerMessage::RateLimited(timer) => {
                 tracing::info!("Rate limited for {} seconds...waiting", timer.as_secs());
                 metrics.rate_limited.inc();
             }
+            IndexerMessage::Error(e) if e.is_fatal() => {
+                tracing::error!("Shutting down due to fatal {} indexer error: {}", realm, e);
+                metrics.errors.inc();
+                fatal_error = Some(e);
+                handles.iter().for_each(IndexerHandle::stop);
+                break;
+            }
+            IndexerMessage::Error(e) => {
+                tracing::warn!("{} indexer error: {}", realm, e);
+                metrics.errors.inc();
+                if matches!(*e, StashApiError::Quarantined { .. }) {
+                    metrics.quarantined_pages.inc();
+                }
+            }
             IndexerMessage::Tick {
                 change_id,
                 stashes,
                 next_change_id,
                 ..
             } => {
-                tracing::info!("Processing {} ({} stashes)", change_id, stashes.len());
+                tracing::info!(
+                    "Processing {} {} ({} stashes)",
+                    realm,
+                    change_id,
+                    stashes.len()
+                );
 
                 metrics
                     .stashes_processed
@@ -90,13 +153,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let next_change_id = next_change_id.clone();
 
                 if !stashes.is_empty() {
+                    // Only flatten items once per tick and only if any sink wants them
+                    let items = sinks
+                        .iter()
+                        .any(|sink| sink.payload() == SinkPayload::Items)
+                        .then(|| transform.item_records(&stashes));
+
                     for sink in sinks.iter_mut() {
-                        sink.handle(&stashes).await?;
+                        match (sink.payload(), &items) {
+                            (SinkPayload::Items, Some(items)) => sink.handle_items(items).await?,
+                            _ => sink.handle(&stashes).await?,
+                        };
                     }
                 }
 
+                // poe.ninja only tracks the PC realm
+                if realm == Realm::Pc {
+                    progress.send_replace(Some(next_change_id.clone()));
+                }
+
                 // Update resumption state at the end of each tick
-                resumption.update(State {
+                resumptions.get_mut(&realm).unwrap().update(State {
                     change_id: change_id.to_string(),
                     next_change_id: next_change_id.to_string(),
                 });
@@ -111,12 +188,120 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    match resumption.save() {
-        Ok(_) => tracing::info!("Saved resumption state"),
-        Err(_) => tracing::error!("Saving resumption state failed"),
+    for (realm, resumption) in resumptions {
+        match resumption.save() {
+            Ok(_) => tracing::info!("Saved resumption state of {}", realm),
+            Err(_) => tracing::error!("Saving resumption state of {} failed", realm),
+        }
+    }
+
+    match fatal_error {
+        Some(e) => Err(e.to_string().into()),
+        None => Ok(()),
+    }
+}
+
+/// PC keeps the state file it had before other realms were supported
+fn resumption_path(realm: Realm) -> PathBuf {
+    match realm {
+        Realm::Pc => "./indexer_state.json".into(),
+        realm => format!("./indexer_state.{realm}.json").into(),
+    }
+}
+
+async fn start_indexer(
+    config: &Configuration,
+    realm: Realm,
+    resumption: &StateWrapper<'_>,
+) -> Result<(IndexerHandle, Receiver<IndexerMessage>), Box<dyn std::error::Error>> {
+    let indexer = setup_indexer(config, realm);
+    match (&config.restart_mode, &resumption.inner) {
+        (RestartMode::Resume, Some(next)) => {
+            let change_id = ChangeId::from_str(&next.next_change_id)?;
+            return Ok(indexer.start_at_change_id(change_id).await?);
+        }
+        (RestartMode::Resume, None) => {
+            tracing::info!(
+                "No previous data found for {}, falling back to RestartMode::Fresh",
+                realm
+            );
+        }
+        (RestartMode::Fresh, _) => {}
+    }
+
+    match config.start_change_ids.get(&realm) {
+        Some(change_id) => {
+            let change_id = ChangeId::from_str(change_id)?;
+            Ok(indexer.start_at_change_id(change_id).await?)
+        }
+        None => indexer.start_with_latest().await,
+    }
+}
+
+/// Hands the messages of a realm over to the merged channel
+async fn forward(
+    realm: Realm,
+    mut rx: Receiver<IndexerMessage>,
+    tx: Sender<(Realm, IndexerMessage)>,
+) {
+    while let Some(msg) = rx.recv().await {
+        if tx.send((realm, msg)).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn setup_indexer(config: &Configuration, realm: Realm) -> Indexer {
+    let mut builder = Indexer::builder(
+        config.client_id.clone(),
+        config.client_secret.clone(),
+        config.developer_mail.clone(),
+    )
+    .realm(realm);
+
+    if let Some(url) = &config.stash_api_base_url {
+        builder = builder.stash_api_base_url(url);
+    }
+    if let Some(url) = &config.oauth_base_url {
+        builder = builder.oauth_base_url(url);
+    }
+    if let Some(url) = &config.poe_ninja_base_url {
+        builder = builder.poe_ninja_base_url(url);
+    }
+    if let Some(buffer_size) = config.buffer_size {
+        builder = builder.buffer_size(buffer_size as usize);
+    }
+    // Change ids of different realms may collide
+    let realm_dir = |dir: &str| match realm {
+        Realm::Pc => PathBuf::from(dir),
+        realm => PathBuf::from(dir).join(realm.to_string()),
+    };
+    if let Some(dir) = &config.record_dir {
+        builder = builder.record_to(realm_dir(dir));
+    }
+    if let Some(retries) = config.decode_retries {
+        builder = builder.decode_retries(retries);
+    }
+    if let Some(dir) = &config.dead_letter_dir {
+        builder = builder.dead_letter_to(realm_dir(dir));
     }
 
-    Ok(())
+    builder.build()
+}
+
+fn setup_lag_monitor(config: &Configuration, metrics: &Metrics) -> LagMonitor {
+    let base_url = config
+        .poe_ninja_base_url
+        .as_deref()
+        .unwrap_or(DEFAULT_POE_NINJA_BASE_URL);
+
+    LagMonitor {
+        poe_ninja: PoeNinjaClient::new(base_url, generate_http_client(None)),
+        interval: Duration::from_secs(config.lag_check_interval_secs.into()),
+        alert_threshold: Duration::from_secs(config.lag_alert_threshold_secs.into()),
+        lag_changes: metrics.lag_changes.clone(),
+        lag_seconds: metrics.lag_seconds.clone(),
+    }
 }
 
 fn setup_signal_handlers() -> Result<Arc<AtomicBool>, Box<dyn std::error::Error>> {
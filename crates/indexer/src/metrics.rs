@@ -1,9 +1,17 @@
-use prometheus_exporter::prometheus::core::{AtomicU64, GenericCounter};
+use prometheus_exporter::prometheus::{
+    core::{AtomicU64, GenericCounter},
+    IntGauge,
+};
 
 pub struct Metrics {
     pub chunks_processed: GenericCounter<AtomicU64>,
     pub stashes_processed: GenericCounter<AtomicU64>,
     pub rate_limited: GenericCounter<AtomicU64>,
+    pub errors: GenericCounter<AtomicU64>,
+    pub quarantined_pages: GenericCounter<AtomicU64>,
+    pub queue_depth: IntGauge,
+    pub lag_changes: IntGauge,
+    pub lag_seconds: IntGauge,
 }
 
 pub fn setup_metrics(port: u32) -> Result<Metrics, Box<dyn std::error::Error>> {
@@ -19,9 +27,25 @@ pub fn setup_metrics(port: u32) -> Result<Metrics, Box<dyn std::error::Error>> {
     let rate_limited =
         prometheus_exporter::prometheus::register_int_counter!("rate_limited", "help")?;
 
+    let errors = prometheus_exporter::prometheus::register_int_counter!("errors", "help")?;
+
+    let quarantined_pages =
+        prometheus_exporter::prometheus::register_int_counter!("quarantined_pages", "help")?;
+
+    let queue_depth = prometheus_exporter::prometheus::register_int_gauge!("queue_depth", "help")?;
+
+    let lag_changes = prometheus_exporter::prometheus::register_int_gauge!("lag_changes", "help")?;
+
+    let lag_seconds = prometheus_exporter::prometheus::register_int_gauge!("lag_seconds", "help")?;
+
     Ok(Metrics {
         chunks_processed,
         stashes_processed,
         rate_limited,
+        errors,
+        quarantined_pages,
+        queue_depth,
+        lag_changes,
+        lag_seconds,
     })
 }
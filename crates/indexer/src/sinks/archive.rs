@@ -0,0 +1,210 @@
+use std::{collections::HashMap, io::Write, str::FromStr};
+
+use chrono::NaiveDateTime;
+use flate2::Compression;
+use stash_api::common::{realm::Realm, schema::OutputSchema, stash::Stash};
+use tracing::error;
+
+use super::parquet;
+
+const TIME_BUCKET: &str = "%Y/%m/%d/%H/%M";
+
+/// How archived stashes are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArchiveFormat {
+    /// Gzipped JSONL files of [`Stash`]es
+    #[default]
+    Json,
+    /// Parquet files with one row per item, see [`parquet::encode`]
+    Parquet,
+}
+
+impl ArchiveFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Json => "json.gz",
+            ArchiveFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ArchiveFormat::Json),
+            "parquet" => Ok(ArchiveFormat::Parquet),
+            _ => Err(format!("expected json or parquet, got {s}")),
+        }
+    }
+}
+
+/// Minute-wide buckets of stashes, shared by the sinks that archive the river as files
+/// under `{prefix}/{YYYY/mm/dd/HH/MM}.{extension}`, see [`ArchiveFormat`].
+#[derive(Debug, Default)]
+pub struct Archive {
+    /// Stashes per archive prefix, see [`archive_prefix`]
+    buffer: HashMap<String, Vec<Stash>>,
+    last_sync: Option<NaiveDateTime>,
+}
+
+impl Archive {
+    /// Whether `payload` starts a new minute, so the buffered stashes should be synced before
+    /// [`Archive::push`]ing it
+    pub fn rolls_over(&mut self, payload: &[Stash]) -> bool {
+        let Some(first) = payload.first() else {
+            return false;
+        };
+
+        match &self.last_sync {
+            Some(last_sync) => {
+                let batch_timestamp = first.created_at;
+                let sync_needed = format!("{}", batch_timestamp.format(TIME_BUCKET))
+                    > format!("{}", last_sync.format(TIME_BUCKET));
+                if sync_needed {
+                    self.last_sync = Some(batch_timestamp);
+                }
+                sync_needed
+            }
+            None => {
+                self.last_sync = Some(chrono::offset::Utc::now().naive_utc());
+                false
+            }
+        }
+    }
+
+    pub fn push(&mut self, payload: &[Stash]) {
+        for stash in payload {
+            if let Some(prefix) = archive_prefix(stash) {
+                self.buffer.entry(prefix).or_default().push(stash.clone());
+            }
+        }
+    }
+
+    /// The file of every prefix with buffered stashes as `(prefix, key, file)`.
+    ///
+    /// `schema` only applies to [`ArchiveFormat::Json`], Parquet columns are always snake_case.
+    pub fn files(
+        &self,
+        format: ArchiveFormat,
+        schema: OutputSchema,
+    ) -> Vec<(String, String, Vec<u8>)> {
+        self.buffer
+            .iter()
+            .filter(|(_, stashes)| !stashes.is_empty())
+            .filter_map(|(prefix, stashes)| {
+                let key = format!(
+                    "{}/{}.{}",
+                    prefix,
+                    stashes.last().unwrap().created_at.format(TIME_BUCKET),
+                    format.extension(),
+                );
+                let file = match format {
+                    ArchiveFormat::Json => encode_json(stashes, schema),
+                    ArchiveFormat::Parquet => match parquet::encode(stashes) {
+                        Ok(file) => file,
+                        Err(e) => {
+                            error!("Error when encoding {} as Parquet: {:?}", key, e);
+                            return None;
+                        }
+                    },
+                };
+                Some((prefix.clone(), key, file))
+            })
+            .collect()
+    }
+
+    /// Drops the stashes of `prefix` once they were synced
+    pub fn clear(&mut self, prefix: &str) {
+        if let Some(entry) = self.buffer.get_mut(prefix) {
+            entry.clear();
+        }
+    }
+}
+
+fn encode_json(stashes: &[Stash], schema: OutputSchema) -> Vec<u8> {
+    let mut w = Vec::new();
+    stashes.iter().for_each(|s| {
+        if let Ok(serialized) = schema.to_vec(s) {
+            w.extend_from_slice(&serialized);
+            w.push(b'\n');
+        }
+    });
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&w).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Archives are stored per league, and per realm for all realms but PC which came first
+fn archive_prefix(stash: &Stash) -> Option<String> {
+    let league = stash.league.as_ref()?;
+    match stash.realm {
+        Realm::Pc => Some(league.clone()),
+        realm => Some(format!("{realm}/{league}")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDateTime;
+    use stash_api::common::{realm::Realm, schema::OutputSchema, stash::Stash};
+
+    use super::{Archive, ArchiveFormat};
+
+    fn stash(id: &str, league: Option<&str>, realm: Realm) -> Stash {
+        Stash {
+            id: id.into(),
+            public: true,
+            account_name: None,
+            stash: None,
+            stash_type: "PremiumStash".into(),
+            items: vec![],
+            rejected_items: vec![],
+            league: league.map(Into::into),
+            realm,
+            created_at: NaiveDateTime::parse_from_str("2024-01-02 03:04:05", "%Y-%m-%d %H:%M:%S")
+                .unwrap(),
+            change_id: "1".into(),
+            next_change_id: "2".into(),
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_files() {
+        let mut archive = Archive::default();
+        archive.push(&[
+            stash("a", Some("Standard"), Realm::Pc),
+            stash("b", Some("Standard"), Realm::Poe2),
+            stash("c", Some("Standard"), Realm::Poe2),
+            // Stashes without a league are not archived
+            stash("d", None, Realm::Pc),
+        ]);
+
+        let mut keys = archive
+            .files(ArchiveFormat::Json, OutputSchema::SnakeCase)
+            .into_iter()
+            .map(|(prefix, key, _)| (prefix, key))
+            .collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                (
+                    "Standard".into(),
+                    "Standard/2024/01/02/03/04.json.gz".into()
+                ),
+                (
+                    "poe2/Standard".into(),
+                    "poe2/Standard/2024/01/02/03/04.json.gz".into()
+                ),
+            ]
+        );
+
+        archive.clear("poe2/Standard");
+        let files = archive.files(ArchiveFormat::Parquet, OutputSchema::SnakeCase);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].1, "Standard/2024/01/02/03/04.parquet");
+    }
+}
@@ -0,0 +1,188 @@
+use std::{io::Write, path::PathBuf};
+
+use async_trait::async_trait;
+use stash_api::common::{schema::OutputSchema, stash::Stash};
+use tracing::{error, info};
+
+use crate::config::{
+    ensure_stashes_payload_from_env, ensure_string_from_env, read_schema_from_env,
+};
+
+use super::{
+    archive::{Archive, ArchiveFormat},
+    sink::Sink,
+};
+
+/// Archives the river to a local directory in the same layout as [`S3Sink`](super::s3::S3Sink).
+///
+/// Files are appended to rather than replaced, so syncing the same minute twice (ie. after
+/// a restart) adds another gzip member to the file instead of losing the earlier one.
+pub struct FileSink {
+    dir: PathBuf,
+    schema: OutputSchema,
+    archive: Archive,
+}
+
+impl FileSink {
+    pub fn new(dir: impl Into<PathBuf>, schema: OutputSchema) -> Self {
+        Self {
+            dir: dir.into(),
+            schema,
+            archive: Default::default(),
+        }
+    }
+
+    async fn sync(&mut self) {
+        info!("Syncing file sink");
+        for (prefix, key, file) in self.archive.files(ArchiveFormat::Json, self.schema) {
+            let path = self.dir.join(key);
+            let res = tokio::task::spawn_blocking(move || append(path, &file)).await;
+
+            match res {
+                Ok(Ok(())) => self.archive.clear(&prefix),
+                Ok(Err(e)) => error!(
+                    "Error when flushing file sink with prefix {}: {:?} - will re-attempt sync next interval",
+                    prefix, e
+                ),
+                Err(e) => error!("Error when flushing file sink with prefix {}: {:?}", prefix, e),
+            }
+        }
+    }
+}
+
+fn append(path: PathBuf, file: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(file)
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    #[tracing::instrument(skip(self, payload), name = "sink-handle-file")]
+    async fn handle(&mut self, payload: &[Stash]) -> Result<usize, Box<dyn std::error::Error>> {
+        if payload.is_empty() {
+            return Ok(0);
+        }
+
+        if self.archive.rolls_over(payload) {
+            self.sync().await;
+        }
+
+        self.archive.push(payload);
+
+        Ok(payload.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.sync().await;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileConfig {
+    pub dir: String,
+    pub schema: OutputSchema,
+}
+
+impl FileConfig {
+    pub fn from_env() -> Result<Option<FileConfig>, std::env::VarError> {
+        if let Ok(string) = std::env::var("FILE_SINK_ENABLED") {
+            if string.to_lowercase().eq("false") || string.eq("0") {
+                return Ok(None);
+            }
+
+            let dir = ensure_string_from_env("FILE_SINK_DIR");
+            let schema = read_schema_from_env("FILE_SINK_SCHEMA");
+            ensure_stashes_payload_from_env("FILE_SINK_PAYLOAD");
+
+            Ok(Some(FileConfig { dir, schema }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::Read, path::Path};
+
+    use chrono::{NaiveDateTime, TimeDelta};
+    use flate2::read::MultiGzDecoder;
+    use stash_api::common::{schema::OutputSchema, stash::Stash};
+
+    use super::FileSink;
+    use crate::sinks::sink::Sink;
+
+    fn stash(id: &str, created_at: NaiveDateTime) -> Stash {
+        Stash {
+            id: id.into(),
+            public: true,
+            account_name: None,
+            stash: None,
+            stash_type: "PremiumStash".into(),
+            items: vec![],
+            rejected_items: vec![],
+            league: Some("Standard".into()),
+            realm: Default::default(),
+            created_at,
+            change_id: "1".into(),
+            next_change_id: "2".into(),
+            extra: Default::default(),
+        }
+    }
+
+    /// The ids of all stashes in the archive of `created_at`
+    fn read(dir: &Path, created_at: NaiveDateTime) -> Vec<String> {
+        let path = dir
+            .join("Standard")
+            .join(format!("{}.json.gz", created_at.format("%Y/%m/%d/%H/%M")));
+        let mut file = String::new();
+        MultiGzDecoder::new(std::fs::File::open(path).unwrap())
+            .read_to_string(&mut file)
+            .unwrap();
+        file.lines()
+            .map(|line| serde_json::from_str::<Stash>(line).unwrap().id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_minutes_roll_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = FileSink::new(dir.path(), OutputSchema::SnakeCase);
+        let now = chrono::Utc::now().naive_utc();
+        let later = now + TimeDelta::minutes(2);
+
+        sink.handle(&[stash("a", now), stash("b", now)])
+            .await
+            .unwrap();
+        // The next minute syncs the previous one
+        sink.handle(&[stash("c", later)]).await.unwrap();
+        assert_eq!(read(dir.path(), now), ["a", "b"]);
+
+        sink.flush().await.unwrap();
+        assert_eq!(read(dir.path(), later), ["c"]);
+    }
+
+    #[tokio::test]
+    async fn test_same_minute_is_appended() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = FileSink::new(dir.path(), OutputSchema::SnakeCase);
+        let created_at =
+            NaiveDateTime::parse_from_str("2024-01-02 03:04:05", "%Y-%m-%d %H:%M:%S").unwrap();
+
+        sink.handle(&[stash("a", created_at)]).await.unwrap();
+        sink.flush().await.unwrap();
+        // ie. after a restart
+        sink.handle(&[stash("b", created_at)]).await.unwrap();
+        sink.flush().await.unwrap();
+
+        assert_eq!(read(dir.path(), created_at), ["a", "b"]);
+    }
+}
@@ -0,0 +1,365 @@
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use async_trait::async_trait;
+use futures::{stream::FuturesUnordered, StreamExt};
+use rdkafka::{
+    message::Message,
+    producer::{FutureProducer, FutureRecord, Producer},
+    util::Timeout,
+    ClientConfig,
+};
+use stash_api::common::{
+    schema::{OutputSchema, SerializeCamelCase},
+    stash::Stash,
+};
+use tracing::error;
+
+use crate::{
+    config::{
+        ensure_string_from_env, read_payload_from_env, read_schema_from_env, read_string_from_env,
+    },
+    transform::ItemRecord,
+};
+
+use super::sink::{Sink, SinkPayload};
+
+/// Placeholder of [`KafkaConfig::topic`] that is replaced with the league of a stash
+const LEAGUE_PLACEHOLDER: &str = "{league}";
+
+/// How long [`Sink::flush`] waits for outstanding deliveries on shutdown
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct KafkaSink {
+    producer: FutureProducer,
+    config: KafkaConfig,
+}
+
+impl KafkaSink {
+    #[tracing::instrument]
+    pub fn connect(config: KafkaConfig) -> Result<Self, rdkafka::error::KafkaError> {
+        // An idempotent producer retries failed sends without duplicating or reordering
+        // messages within a partition
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", &config.brokers)
+            .set("enable.idempotence", "true")
+            .set("acks", "all")
+            .set("compression.type", "lz4")
+            .create()?;
+
+        Ok(Self { producer, config })
+    }
+
+    /// The topic for stashes of `league`, stashes without a league go to `"unknown"`.
+    ///
+    /// Topic names may only contain `[a-zA-Z0-9._-]`, so `Hardcore Settlers` turns into
+    /// `Hardcore_Settlers`.
+    fn topic(&self, league: Option<&str>) -> String {
+        let league = league
+            .unwrap_or("unknown")
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' | '-' => c,
+                _ => '_',
+            })
+            .collect::<String>();
+        self.config.topic.replace(LEAGUE_PLACEHOLDER, &league)
+    }
+
+    /// One message per record, keyed by its stash id so that all updates of a stash end up
+    /// in the same partition in the order they were indexed
+    fn per_stash<T: Record>(
+        &self,
+        payload: &[T],
+    ) -> Result<Vec<(String, String, Vec<u8>)>, serde_json::Error> {
+        payload
+            .iter()
+            .map(|record| {
+                Ok((
+                    self.topic(record.league()),
+                    record.stash_id().to_string(),
+                    self.config.schema.to_vec(record)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// One message per topic holding all of its records of the tick, keyed by the change id
+    fn per_tick<T: Record>(
+        &self,
+        payload: &[T],
+    ) -> Result<Vec<(String, String, Vec<u8>)>, serde_json::Error> {
+        let mut topics = HashMap::<String, Vec<&T>>::new();
+        for record in payload {
+            topics
+                .entry(self.topic(record.league()))
+                .or_default()
+                .push(record);
+        }
+
+        topics
+            .into_iter()
+            .map(|(topic, records)| {
+                let key = records[0].change_id().to_string();
+                Ok((topic, key, self.config.schema.to_vec(&records)?))
+            })
+            .collect()
+    }
+
+    async fn publish<T: Record>(&self, payload: &[T]) -> Result<usize, Box<dyn std::error::Error>> {
+        let messages = match self.config.mode {
+            KafkaMessageMode::Stash => self.per_stash(payload)?,
+            KafkaMessageMode::Tick => self.per_tick(payload)?,
+        };
+
+        let mut deliveries = messages
+            .iter()
+            .map(|(topic, key, message)| {
+                self.producer.send(
+                    FutureRecord::to(topic).key(key).payload(message),
+                    // Wait for room in the local queue on large ticks
+                    Timeout::Never,
+                )
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        // The producer already retried failed messages, so the tick has to be given up on to
+        // resume from it later instead of losing its stashes
+        let mut failures = 0;
+        while let Some(delivery) = deliveries.next().await {
+            if let Err((e, message)) = delivery {
+                error!(
+                    "Error when delivering Kafka message to {}: {:?}",
+                    message.topic(),
+                    e
+                );
+                failures += 1;
+            }
+        }
+
+        if failures > 0 {
+            return Err(format!("{failures} Kafka messages could not be delivered").into());
+        }
+
+        Ok(payload.len())
+    }
+}
+
+/// What is published to Kafka, routed by its league and stash id
+trait Record: SerializeCamelCase + Sync {
+    fn league(&self) -> Option<&str>;
+    fn stash_id(&self) -> &str;
+    fn change_id(&self) -> &str;
+}
+
+impl Record for Stash {
+    fn league(&self) -> Option<&str> {
+        self.league.as_deref()
+    }
+
+    fn stash_id(&self) -> &str {
+        &self.id
+    }
+
+    fn change_id(&self) -> &str {
+        &self.change_id
+    }
+}
+
+impl Record for ItemRecord {
+    fn league(&self) -> Option<&str> {
+        self.league.as_deref()
+    }
+
+    fn stash_id(&self) -> &str {
+        &self.stash_id
+    }
+
+    fn change_id(&self) -> &str {
+        &self.change_id
+    }
+}
+
+#[async_trait]
+impl Sink for KafkaSink {
+    #[tracing::instrument(skip(self, payload), name = "sink-handle-kafka")]
+    async fn handle(&mut self, payload: &[Stash]) -> Result<usize, Box<dyn std::error::Error>> {
+        self.publish(payload).await
+    }
+
+    #[tracing::instrument(skip(self, payload), name = "sink-handle-items-kafka")]
+    async fn handle_items(
+        &mut self,
+        payload: &[ItemRecord],
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        self.publish(payload).await
+    }
+
+    fn payload(&self) -> SinkPayload {
+        self.config.payload
+    }
+
+    async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        // Flushing blocks until all deliveries finished or timed out
+        let producer = self.producer.clone();
+        tokio::task::spawn_blocking(move || producer.flush(FLUSH_TIMEOUT)).await??;
+        Ok(())
+    }
+}
+
+/// What a single Kafka message holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KafkaMessageMode {
+    /// A single [`Stash`] or [`ItemRecord`]
+    #[default]
+    Stash,
+    /// A JSON array of all [`Stash`]es or [`ItemRecord`]s of a tick
+    Tick,
+}
+
+impl FromStr for KafkaMessageMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stash" => Ok(KafkaMessageMode::Stash),
+            "tick" => Ok(KafkaMessageMode::Tick),
+            _ => Err(format!("expected stash or tick, got {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KafkaConfig {
+    pub brokers: String,
+    /// Can contain `{league}` to publish to a topic per league
+    pub topic: String,
+    pub mode: KafkaMessageMode,
+    pub schema: OutputSchema,
+    pub payload: SinkPayload,
+}
+
+impl KafkaConfig {
+    pub fn from_env() -> Result<Option<KafkaConfig>, std::env::VarError> {
+        if let Ok(string) = std::env::var("KAFKA_SINK_ENABLED") {
+            if string.to_lowercase().eq("false") || string.eq("0") {
+                return Ok(None);
+            }
+
+            let brokers = ensure_string_from_env("KAFKA_BROKERS");
+            let topic = read_string_from_env("KAFKA_TOPIC").unwrap_or("poe-stash-indexer".into());
+            let mode = read_string_from_env("KAFKA_SINK_MODE")
+                .map(|s| {
+                    s.parse()
+                        .unwrap_or_else(|e| panic!("Invalid KAFKA_SINK_MODE: {e}"))
+                })
+                .unwrap_or_default();
+            let schema = read_schema_from_env("KAFKA_SINK_SCHEMA");
+            let payload = read_payload_from_env("KAFKA_SINK_PAYLOAD");
+
+            Ok(Some(KafkaConfig {
+                brokers,
+                topic,
+                mode,
+                schema,
+                payload,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use stash_api::common::{schema::OutputSchema, stash::Stash};
+
+    use super::{KafkaConfig, KafkaMessageMode, KafkaSink};
+    use crate::sinks::sink::SinkPayload;
+
+    fn sink() -> KafkaSink {
+        // Connecting does not reach out to the brokers yet
+        KafkaSink::connect(KafkaConfig {
+            brokers: "127.0.0.1:1".into(),
+            topic: "poe-{league}".into(),
+            mode: KafkaMessageMode::Stash,
+            schema: OutputSchema::SnakeCase,
+            payload: SinkPayload::Stashes,
+        })
+        .unwrap()
+    }
+
+    fn stash(id: &str, league: Option<&str>) -> Stash {
+        Stash {
+            id: id.into(),
+            public: true,
+            account_name: None,
+            stash: None,
+            stash_type: "PremiumStash".into(),
+            items: vec![],
+            rejected_items: vec![],
+            league: league.map(Into::into),
+            realm: Default::default(),
+            created_at: Default::default(),
+            change_id: "1".into(),
+            next_change_id: "2".into(),
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_topic() {
+        let sink = sink();
+        assert_eq!(sink.topic(Some("Standard")), "poe-Standard");
+        assert_eq!(
+            sink.topic(Some("Hardcore Settlers")),
+            "poe-Hardcore_Settlers"
+        );
+        assert_eq!(
+            sink.topic(Some("Mercenaries (PL123)")),
+            "poe-Mercenaries__PL123_"
+        );
+        assert_eq!(sink.topic(Some("hc.ssf-2")), "poe-hc.ssf-2");
+        assert_eq!(sink.topic(None), "poe-unknown");
+    }
+
+    #[test]
+    fn test_per_stash() {
+        let payload = [stash("a", Some("Standard")), stash("b", Some("Hardcore"))];
+
+        let messages = sink().per_stash(&payload).unwrap();
+        let routing = messages
+            .iter()
+            .map(|(topic, key, _)| (topic.as_str(), key.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(routing, [("poe-Standard", "a"), ("poe-Hardcore", "b")]);
+        assert_eq!(
+            serde_json::from_slice::<Stash>(&messages[1].2).unwrap(),
+            payload[1]
+        );
+    }
+
+    #[test]
+    fn test_per_tick() {
+        let payload = [
+            stash("a", Some("Standard")),
+            stash("b", Some("Hardcore")),
+            stash("c", Some("Standard")),
+        ];
+
+        let mut messages = sink().per_tick(&payload).unwrap();
+        messages.sort();
+        assert_eq!(messages.len(), 2);
+
+        let (topic, key, message) = &messages[1];
+        assert_eq!((topic.as_str(), key.as_str()), ("poe-Standard", "1"));
+        let stashes = serde_json::from_slice::<Vec<Stash>>(message).unwrap();
+        assert_eq!(stashes, [payload[0].clone(), payload[2].clone()]);
+
+        let (topic, _, message) = &messages[0];
+        assert_eq!(topic, "poe-Hardcore");
+        assert_eq!(
+            serde_json::from_slice::<Vec<Stash>>(message).unwrap().len(),
+            1
+        );
+    }
+}
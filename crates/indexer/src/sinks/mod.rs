@@ -1,3 +1,8 @@
+pub mod archive;
+pub mod file;
+pub mod kafka;
+pub mod parquet;
+pub mod postgres;
 pub mod rabbitmq;
 pub mod s3;
 pub mod sink;
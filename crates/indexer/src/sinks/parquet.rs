@@ -0,0 +1,273 @@
+use std::sync::{Arc, LazyLock};
+
+use arrow_array::{
+    builder::{
+        BooleanBuilder, Int32Builder, ListBuilder, StringBuilder, TimestampMillisecondBuilder,
+    },
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression, ZstdLevel},
+    errors::ParquetError,
+    file::properties::WriterProperties,
+};
+use stash_api::common::stash::Stash;
+
+/// One row per item, joined with the stash it is listed in
+static SCHEMA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    let mods = || DataType::List(Arc::new(Field::new_list_field(DataType::Utf8, false)));
+
+    Arc::new(Schema::new(vec![
+        Field::new("stash_id", DataType::Utf8, false),
+        Field::new("account_name", DataType::Utf8, true),
+        Field::new("stash", DataType::Utf8, true),
+        Field::new("league", DataType::Utf8, true),
+        Field::new("realm", DataType::Utf8, false),
+        Field::new("change_id", DataType::Utf8, false),
+        Field::new(
+            "created_at",
+            DataType::Timestamp(TimeUnit::Millisecond, None),
+            false,
+        ),
+        Field::new("item_id", DataType::Utf8, true),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("type_line", DataType::Utf8, false),
+        Field::new("base_type", DataType::Utf8, false),
+        Field::new("note", DataType::Utf8, true),
+        Field::new("stack_size", DataType::Int32, true),
+        Field::new("ilvl", DataType::Int32, false),
+        Field::new("frame_type", DataType::Int32, true),
+        Field::new("identified", DataType::Boolean, false),
+        Field::new("corrupted", DataType::Boolean, false),
+        Field::new("implicit_mods", mods(), false),
+        Field::new("explicit_mods", mods(), false),
+        Field::new("crafted_mods", mods(), false),
+        Field::new("fractured_mods", mods(), false),
+        Field::new("enchant_mods", mods(), false),
+    ]))
+});
+
+/// Encodes the items of `stashes` as a Parquet file with the flat schema of [`SCHEMA`], so
+/// that archives can be queried directly, ie. with DuckDB or Athena.
+pub fn encode(stashes: &[Stash]) -> Result<Vec<u8>, ParquetError> {
+    let mut stash_id = StringBuilder::new();
+    let mut account_name = StringBuilder::new();
+    let mut stash_name = StringBuilder::new();
+    let mut league = StringBuilder::new();
+    let mut realm = StringBuilder::new();
+    let mut change_id = StringBuilder::new();
+    let mut created_at = TimestampMillisecondBuilder::new();
+    let mut item_id = StringBuilder::new();
+    let mut name = StringBuilder::new();
+    let mut type_line = StringBuilder::new();
+    let mut base_type = StringBuilder::new();
+    let mut note = StringBuilder::new();
+    let mut stack_size = Int32Builder::new();
+    let mut ilvl = Int32Builder::new();
+    let mut frame_type = Int32Builder::new();
+    let mut identified = BooleanBuilder::new();
+    let mut corrupted = BooleanBuilder::new();
+    let mut mods = std::array::from_fn::<_, 5, _>(|_| {
+        ListBuilder::new(StringBuilder::new())
+            .with_field(Field::new_list_field(DataType::Utf8, false))
+    });
+
+    for stash in stashes {
+        for item in &stash.items {
+            stash_id.append_value(&stash.id);
+            account_name.append_option(stash.account_name.as_ref());
+            stash_name.append_option(stash.stash.as_ref());
+            league.append_option(stash.league.as_ref());
+            realm.append_value(stash.realm.to_string());
+            change_id.append_value(&stash.change_id);
+            created_at.append_value(stash.created_at.and_utc().timestamp_millis());
+            item_id.append_option(item.id.as_ref());
+            name.append_value(&item.name);
+            type_line.append_value(&item.type_line);
+            base_type.append_value(&item.base_type);
+            note.append_option(item.note.as_ref());
+            stack_size.append_option(item.stack_size.map(i32::from));
+            ilvl.append_value(item.item_level.unwrap_or(item.ilvl).into());
+            frame_type.append_option(item.frame_type.map(i32::from));
+            identified.append_value(item.identified);
+            corrupted.append_value(item.corrupted.unwrap_or(false));
+
+            let item_mods = [
+                &item.implicit_mods,
+                &item.explicit_mods,
+                &item.crafted_mods,
+                &item.fractured_mods,
+                &item.enchant_mods,
+            ];
+            for (builder, item_mods) in mods.iter_mut().zip(item_mods) {
+                builder.append_value(item_mods.iter().flatten().map(Some));
+            }
+        }
+    }
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(stash_id.finish()),
+        Arc::new(account_name.finish()),
+        Arc::new(stash_name.finish()),
+        Arc::new(league.finish()),
+        Arc::new(realm.finish()),
+        Arc::new(change_id.finish()),
+        Arc::new(created_at.finish()),
+        Arc::new(item_id.finish()),
+        Arc::new(name.finish()),
+        Arc::new(type_line.finish()),
+        Arc::new(base_type.finish()),
+        Arc::new(note.finish()),
+        Arc::new(stack_size.finish()),
+        Arc::new(ilvl.finish()),
+        Arc::new(frame_type.finish()),
+        Arc::new(identified.finish()),
+        Arc::new(corrupted.finish()),
+    ];
+    columns.extend(
+        mods.iter_mut()
+            .map(|builder| Arc::new(builder.finish()) as ArrayRef),
+    );
+
+    let batch = RecordBatch::try_new(SCHEMA.clone(), columns)?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::ZSTD(ZstdLevel::default()))
+        .build();
+
+    let mut writer = ArrowWriter::try_new(Vec::new(), SCHEMA.clone(), Some(properties))?;
+    writer.write(&batch)?;
+    writer.into_inner()
+}
+
+#[cfg(test)]
+mod test {
+    use arrow_array::{
+        cast::AsArray,
+        types::{Int32Type, TimestampMillisecondType},
+        Array,
+    };
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::json;
+    use stash_api::common::stash::Stash;
+
+    use super::encode;
+
+    fn stash(id: &str, items: Vec<serde_json::Value>) -> Stash {
+        Stash {
+            id: id.into(),
+            public: true,
+            account_name: Some("foo".into()),
+            stash: None,
+            stash_type: "PremiumStash".into(),
+            items: items
+                .into_iter()
+                .map(|item| serde_json::from_value(item).unwrap())
+                .collect(),
+            rejected_items: vec![],
+            league: Some("Standard".into()),
+            realm: Default::default(),
+            created_at: Default::default(),
+            change_id: "1".into(),
+            next_change_id: "2".into(),
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let stashes = [
+            stash(
+                "a",
+                vec![
+                    json!({
+                        "verified": false, "w": 1, "h": 1, "icon": "", "name": "",
+                        "typeLine": "Chaos Orb", "baseType": "Chaos Orb", "identified": true,
+                        "ilvl": 0, "stackSize": 3, "frameType": 5, "note": "~price 1 chaos",
+                    }),
+                    json!({
+                        "verified": false, "w": 1, "h": 1, "icon": "", "name": "Doom Hold",
+                        "typeLine": "Iron Ring", "baseType": "Iron Ring", "identified": true,
+                        "ilvl": 80, "itemLevel": 84, "corrupted": true,
+                        "explicitMods": ["+10 to Strength", "+20 to maximum Life"],
+                    }),
+                ],
+            ),
+            stash("b", vec![]),
+        ];
+
+        let file = bytes::Bytes::from(encode(&stashes).unwrap());
+        let batches = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+
+        // Stashes without items have no rows
+        assert_eq!(batch.num_rows(), 2);
+        let strings = |name: &str| {
+            let column = batch.column_by_name(name).unwrap().as_string::<i32>();
+            (0..column.len())
+                .map(|i| column.is_valid(i).then(|| column.value(i).to_string()))
+                .collect::<Vec<_>>()
+        };
+        let integers = |name: &str| {
+            let column = batch.column_by_name(name).unwrap();
+            column
+                .as_primitive::<Int32Type>()
+                .iter()
+                .collect::<Vec<_>>()
+        };
+        let mods = |name: &str| {
+            let column = batch.column_by_name(name).unwrap().as_list::<i32>();
+            column
+                .iter()
+                .map(|mods| {
+                    let mods = mods.unwrap();
+                    let mods = mods.as_string::<i32>();
+                    mods.iter().map(|m| m.unwrap().to_string()).collect()
+                })
+                .collect::<Vec<Vec<_>>>()
+        };
+
+        assert_eq!(strings("stash_id"), [Some("a".into()), Some("a".into())]);
+        assert_eq!(
+            strings("account_name"),
+            [Some("foo".into()), Some("foo".into())]
+        );
+        assert_eq!(strings("stash"), [None, None]);
+        assert_eq!(strings("realm"), [Some("pc".into()), Some("pc".into())]);
+        assert_eq!(strings("note"), [Some("~price 1 chaos".into()), None]);
+        assert_eq!(strings("name"), [Some("".into()), Some("Doom Hold".into())]);
+        assert_eq!(integers("stack_size"), [Some(3), None]);
+        assert_eq!(integers("frame_type"), [Some(5), None]);
+        // The item level of GGG's API takes precedence over `ilvl`
+        assert_eq!(integers("ilvl"), [Some(0), Some(84)]);
+        assert_eq!(
+            batch
+                .column_by_name("corrupted")
+                .unwrap()
+                .as_boolean()
+                .iter()
+                .collect::<Vec<_>>(),
+            [Some(false), Some(true)]
+        );
+        assert_eq!(
+            batch
+                .column_by_name("created_at")
+                .unwrap()
+                .as_primitive::<TimestampMillisecondType>()
+                .value(0),
+            0
+        );
+        assert_eq!(
+            mods("explicit_mods"),
+            [vec![], vec!["+10 to Strength", "+20 to maximum Life"]]
+        );
+        assert_eq!(mods("implicit_mods"), [Vec::<String>::new(), vec![]]);
+    }
+}
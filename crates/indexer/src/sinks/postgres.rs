@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use stash_api::common::stash::Stash;
+
+use crate::config::{ensure_stashes_payload_from_env, ensure_string_from_env};
+
+use super::sink::Sink;
+
+/// Upserts stashes by their id, so that `stashes` and `items` always reflect the latest
+/// state of each public stash.
+///
+/// Every payload is copied into temporary tables via `COPY` and merged from there in one
+/// transaction, which is a lot faster than inserting stashes and items row by row.
+pub struct PostgresSink {
+    pool: PgPool,
+}
+
+impl PostgresSink {
+    #[tracing::instrument(skip(config))]
+    pub async fn connect(config: &PostgresConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&config.connection_url)
+            .await?;
+
+        sqlx::migrate!("./migrations").run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl Sink for PostgresSink {
+    #[tracing::instrument(skip(self, payload), name = "sink-handle-postgres")]
+    async fn handle(&mut self, payload: &[Stash]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut stashes = Vec::new();
+        let mut items = Vec::new();
+        for stash in latest(payload) {
+            write_row(
+                &mut stashes,
+                &[
+                    Some(stash.id.as_str()),
+                    Some(if stash.public { "t" } else { "f" }),
+                    stash.account_name.as_deref(),
+                    stash.stash.as_deref(),
+                    Some(&stash.stash_type),
+                    stash.league.as_deref(),
+                    Some(&stash.realm.to_string()),
+                    Some(&stash.change_id),
+                    Some(&stash.next_change_id),
+                    Some(&stash.created_at.to_string()),
+                ],
+            );
+
+            for (position, item) in stash.items.iter().enumerate() {
+                write_row(
+                    &mut items,
+                    &[
+                        Some(stash.id.as_str()),
+                        Some(&position.to_string()),
+                        item.id.as_deref(),
+                        Some(&item.name),
+                        Some(&item.type_line),
+                        Some(&item.base_type),
+                        item.note.as_deref(),
+                        Some(&serde_json::to_string(item)?),
+                    ],
+                );
+            }
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("CREATE TEMP TABLE stashes_staging (LIKE stashes) ON COMMIT DROP")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("CREATE TEMP TABLE items_staging (LIKE items) ON COMMIT DROP")
+            .execute(&mut *tx)
+            .await?;
+
+        let mut copy = tx.copy_in_raw("COPY stashes_staging FROM STDIN").await?;
+        copy.send(stashes).await?;
+        copy.finish().await?;
+
+        let mut copy = tx.copy_in_raw("COPY items_staging FROM STDIN").await?;
+        copy.send(items).await?;
+        copy.finish().await?;
+
+        sqlx::query(
+            "INSERT INTO stashes SELECT * FROM stashes_staging
+            ON CONFLICT (id) DO UPDATE SET
+                public = EXCLUDED.public,
+                account_name = EXCLUDED.account_name,
+                stash = EXCLUDED.stash,
+                stash_type = EXCLUDED.stash_type,
+                league = EXCLUDED.league,
+                realm = EXCLUDED.realm,
+                change_id = EXCLUDED.change_id,
+                next_change_id = EXCLUDED.next_change_id,
+                created_at = EXCLUDED.created_at",
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM items WHERE stash_id IN (SELECT id FROM stashes_staging)")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO items SELECT * FROM items_staging")
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(payload.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// The latest update of every stash in `payload`, as a stash can only be upserted once per
+/// statement
+fn latest(payload: &[Stash]) -> Vec<&Stash> {
+    payload
+        .iter()
+        .map(|stash| (stash.id.as_str(), stash))
+        .collect::<HashMap<_, _>>()
+        .into_values()
+        .collect()
+}
+
+/// Appends a row in the text format of `COPY`, where `None` is `NULL`
+fn write_row(buffer: &mut Vec<u8>, columns: &[Option<&str>]) {
+    for (i, column) in columns.iter().enumerate() {
+        if i > 0 {
+            buffer.push(b'\t');
+        }
+
+        match column {
+            None => buffer.extend_from_slice(b"\\N"),
+            Some(value) => {
+                for byte in value.bytes() {
+                    match byte {
+                        b'\\' => buffer.extend_from_slice(b"\\\\"),
+                        b'\t' => buffer.extend_from_slice(b"\\t"),
+                        b'\n' => buffer.extend_from_slice(b"\\n"),
+                        b'\r' => buffer.extend_from_slice(b"\\r"),
+                        _ => buffer.push(byte),
+                    }
+                }
+            }
+        }
+    }
+    buffer.push(b'\n');
+}
+
+#[derive(Debug, Clone)]
+pub struct PostgresConfig {
+    pub connection_url: String,
+}
+
+impl PostgresConfig {
+    pub fn from_env() -> Result<Option<PostgresConfig>, std::env::VarError> {
+        if let Ok(string) = std::env::var("POSTGRES_SINK_ENABLED") {
+            if string.to_lowercase().eq("false") || string.eq("0") {
+                return Ok(None);
+            }
+
+            ensure_stashes_payload_from_env("POSTGRES_SINK_PAYLOAD");
+
+            Ok(Some(PostgresConfig {
+                connection_url: ensure_string_from_env("POSTGRES_URL"),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use stash_api::common::stash::Stash;
+
+    use super::{latest, write_row};
+
+    fn stash(id: &str, change_id: &str) -> Stash {
+        Stash {
+            id: id.into(),
+            public: true,
+            account_name: None,
+            stash: None,
+            stash_type: "PremiumStash".into(),
+            items: vec![],
+            rejected_items: vec![],
+            league: None,
+            realm: Default::default(),
+            created_at: Default::default(),
+            change_id: change_id.into(),
+            next_change_id: "3".into(),
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_write_row() {
+        let row = |columns: &[Option<&str>]| {
+            let mut buffer = Vec::new();
+            write_row(&mut buffer, columns);
+            String::from_utf8(buffer).unwrap()
+        };
+
+        assert_eq!(row(&[Some("a"), None, Some("")]), "a\t\\N\t\n");
+        assert_eq!(row(&[Some("a\tb")]), "a\\tb\n");
+        assert_eq!(row(&[Some("a\nb")]), "a\\nb\n");
+        assert_eq!(row(&[Some("a\rb")]), "a\\rb\n");
+        assert_eq!(row(&[Some("a\\b")]), "a\\\\b\n");
+        // A literal `\N` is data, not NULL
+        assert_eq!(row(&[Some("\\N")]), "\\\\N\n");
+    }
+
+    #[test]
+    fn test_latest() {
+        let payload = [stash("a", "1"), stash("b", "1"), stash("a", "2")];
+
+        let mut latest = latest(&payload)
+            .into_iter()
+            .map(|stash| (stash.id.as_str(), stash.change_id.as_str()))
+            .collect::<Vec<_>>();
+        latest.sort();
+        assert_eq!(latest, [("a", "2"), ("b", "1")]);
+    }
+}
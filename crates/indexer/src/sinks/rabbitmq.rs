@@ -1,10 +1,15 @@
 use async_trait::async_trait;
 use lapin::{options::BasicPublishOptions, BasicProperties, Channel, Connection};
-use stash_api::common::stash::Stash;
+use stash_api::common::{schema::OutputSchema, stash::Stash};
 
-use crate::config::{ensure_string_from_env, read_string_from_env};
+use crate::{
+    config::{
+        ensure_string_from_env, read_payload_from_env, read_schema_from_env, read_string_from_env,
+    },
+    transform::ItemRecord,
+};
 
-use super::sink::Sink;
+use super::sink::{Sink, SinkPayload};
 
 const EXCHANGE: &str = "amq.fanout";
 
@@ -32,25 +37,42 @@ impl RabbitMqSink {
             config,
         })
     }
-}
-
-#[async_trait]
-impl Sink for RabbitMqSink {
-    #[tracing::instrument(skip(self, payload), name = "sink-handle-rabbitmq")]
-    async fn handle(&mut self, payload: &[Stash]) -> Result<usize, Box<dyn std::error::Error>> {
-        let serialized = serde_json::to_string(payload)?;
 
+    async fn publish(&self, serialized: &[u8]) -> Result<(), lapin::Error> {
         self.channel
             .basic_publish(
                 EXCHANGE,
                 &self.config.producer_routing_key,
                 BasicPublishOptions::default(),
-                serialized.as_bytes(),
+                serialized,
                 BasicProperties::default(),
             )
             .await
-            .map(|_| payload.len())
-            .map_err(|e| e.into())
+            .map(|_| ())
+    }
+}
+
+#[async_trait]
+impl Sink for RabbitMqSink {
+    #[tracing::instrument(skip(self, payload), name = "sink-handle-rabbitmq")]
+    async fn handle(&mut self, payload: &[Stash]) -> Result<usize, Box<dyn std::error::Error>> {
+        let serialized = self.config.schema.to_vec(payload)?;
+        self.publish(&serialized).await?;
+        Ok(payload.len())
+    }
+
+    #[tracing::instrument(skip(self, payload), name = "sink-handle-items-rabbitmq")]
+    async fn handle_items(
+        &mut self,
+        payload: &[ItemRecord],
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let serialized = self.config.schema.to_vec(payload)?;
+        self.publish(&serialized).await?;
+        Ok(payload.len())
+    }
+
+    fn payload(&self) -> SinkPayload {
+        self.config.payload
     }
 
     async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
@@ -62,6 +84,8 @@ impl Sink for RabbitMqSink {
 pub struct RabbitMqConfig {
     pub connection_url: String,
     pub producer_routing_key: String,
+    pub schema: OutputSchema,
+    pub payload: SinkPayload,
 }
 
 impl RabbitMqConfig {
@@ -74,10 +98,14 @@ impl RabbitMqConfig {
             let connection_url = ensure_string_from_env("RABBITMQ_URL");
             let producer_routing_key = read_string_from_env("RABBITMQ_PRODUCER_ROUTING_KEY")
                 .unwrap_or("poe-stash-indexer".into());
+            let schema = read_schema_from_env("RABBITMQ_SINK_SCHEMA");
+            let payload = read_payload_from_env("RABBITMQ_SINK_PAYLOAD");
 
             Ok(Some(RabbitMqConfig {
                 connection_url,
                 producer_routing_key,
+                schema,
+                payload,
             }))
         } else {
             Ok(None)
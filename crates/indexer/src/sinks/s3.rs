@@ -1,29 +1,29 @@
-use std::{
-    collections::HashMap,
-    fmt::Debug,
-    io::Write,
-    sync::{Arc, RwLock},
-};
+use std::fmt::Debug;
 
 use async_trait::async_trait;
 use aws_config::BehaviorVersion;
 use aws_sdk_s3::{primitives::ByteStream, Client};
 use aws_types::region::Region;
-use chrono::NaiveDateTime;
-use flate2::Compression;
 use futures::{stream::FuturesUnordered, StreamExt};
-use stash_api::common::stash::Stash;
+use stash_api::common::{schema::OutputSchema, stash::Stash};
 use tracing::{error, info};
 
-use crate::config::ensure_string_from_env;
+use crate::config::{
+    ensure_stashes_payload_from_env, ensure_string_from_env, read_schema_from_env,
+    read_string_from_env,
+};
 
-use super::sink::Sink;
+use super::{
+    archive::{Archive, ArchiveFormat},
+    sink::Sink,
+};
 
 pub struct S3Sink {
     client: Client,
     bucket: String,
-    buffer: Arc<RwLock<HashMap<String, Vec<Stash>>>>,
-    last_sync: Option<NaiveDateTime>,
+    format: ArchiveFormat,
+    schema: OutputSchema,
+    archive: Archive,
 }
 
 impl S3Sink {
@@ -31,6 +31,8 @@ impl S3Sink {
     pub async fn connect(
         bucket: impl Into<String> + Debug,
         region: impl Into<String> + Debug,
+        format: ArchiveFormat,
+        schema: OutputSchema,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let bucket = bucket.into();
 
@@ -44,8 +46,9 @@ impl S3Sink {
         Ok(Self {
             client,
             bucket,
-            buffer: Default::default(),
-            last_sync: None,
+            format,
+            schema,
+            archive: Default::default(),
         })
     }
 
@@ -53,60 +56,36 @@ impl S3Sink {
         // todo: error handling of s3 client
         // todo: sync in another tokio task that does not block the rest
         info!("Syncing S3 Sink");
-        let tasks = self
-            .buffer
-            .read()
-            .unwrap()
-            .iter()
-            .filter(|(_, stashes)| !stashes.is_empty())
-            .map(|(league, stashes)| {
-                let key = format!(
-                    "{}/{}.json.gz",
-                    league,
-                    stashes.last().unwrap().created_at.format(TIME_BUCKET),
-                );
-                let mut w = Vec::new();
-                stashes.iter().for_each(|s| {
-                    let _ = jsonl::write(&mut w, s);
-                });
-                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Compression::best());
-                encoder.write_all(&w).unwrap();
-                let compressed = encoder.finish().unwrap();
-                let payload = ByteStream::from(compressed);
-                (league.clone(), key, payload)
-            })
-            .collect::<Vec<_>>();
-
-        let mut tasks = tasks
+        let mut tasks = self
+            .archive
+            .files(self.format, self.schema)
             .into_iter()
-            .map(|(league, key, payload)| {
+            .map(|(prefix, key, file)| {
                 let f = self
                     .client
                     .put_object()
                     .bucket(&self.bucket)
                     .key(key)
-                    .body(payload)
+                    .body(ByteStream::from(file))
                     .send();
 
-                async { (league, f.await) }
+                async { (prefix, f.await) }
             })
             .collect::<FuturesUnordered<_>>();
 
-        while let Some((league, res)) = tasks.next().await {
+        while let Some((prefix, res)) = tasks.next().await {
             if let Err(e) = res {
                 error!(
-                    "Error when flushing S3 sink with league {}: {:?} - will re-attempt sync next interval",
-                    league, e
+                    "Error when flushing S3 sink with prefix {}: {:?} - will re-attempt sync next interval",
+                    prefix, e
                 )
-            } else if let Some(entry) = self.buffer.write().unwrap().get_mut(&league) {
-                entry.clear();
+            } else {
+                self.archive.clear(&prefix);
             }
         }
     }
 }
 
-const TIME_BUCKET: &str = "%Y/%m/%d/%H/%M";
-
 #[async_trait]
 impl Sink for S3Sink {
     #[tracing::instrument(skip(self, payload), name = "sink-handle-s3")]
@@ -115,37 +94,11 @@ impl Sink for S3Sink {
             return Ok(0);
         }
 
-        let should_sync = match &self.last_sync {
-            Some(last_sync) => {
-                let batch_timestamp = payload.first().unwrap().created_at;
-                let sync_needed = format!("{}", batch_timestamp.format(TIME_BUCKET))
-                    > format!("{}", last_sync.format(TIME_BUCKET));
-                if sync_needed {
-                    self.last_sync = Some(batch_timestamp);
-                }
-                sync_needed
-            }
-            None => {
-                self.last_sync = Some(chrono::offset::Utc::now().naive_utc());
-                false
-            }
-        };
-
-        if should_sync {
+        if self.archive.rolls_over(payload) {
             self.sync().await;
         }
 
-        for stash in payload {
-            if let Some(league) = &stash.league {
-                self.buffer
-                    .write()
-                    .unwrap()
-                    .entry(league.clone())
-                    .or_default()
-                    // todo: box stash records
-                    .push(stash.clone());
-            }
-        }
+        self.archive.push(payload);
 
         Ok(payload.len())
     }
@@ -160,6 +113,8 @@ impl Sink for S3Sink {
 pub struct S3Config {
     pub bucket_name: String,
     pub region: String,
+    pub format: ArchiveFormat,
+    pub schema: OutputSchema,
 }
 
 impl S3Config {
@@ -171,10 +126,20 @@ impl S3Config {
 
             let bucket_name = ensure_string_from_env("S3_SINK_BUCKET_NAME");
             let region = ensure_string_from_env("S3_SINK_REGION");
+            let format = read_string_from_env("S3_SINK_FORMAT")
+                .map(|s| {
+                    s.parse()
+                        .unwrap_or_else(|e| panic!("Invalid S3_SINK_FORMAT: {e}"))
+                })
+                .unwrap_or_default();
+            let schema = read_schema_from_env("S3_SINK_SCHEMA");
+            ensure_stashes_payload_from_env("S3_SINK_PAYLOAD");
 
             Ok(Some(S3Config {
                 bucket_name,
                 region,
+                format,
+                schema,
             }))
         } else {
             Ok(None)
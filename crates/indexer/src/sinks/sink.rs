@@ -1,19 +1,60 @@
+use std::str::FromStr;
+
 use async_trait::async_trait;
 use stash_api::common::stash::Stash;
 
 use crate::{
     config::Configuration,
-    sinks::{rabbitmq::RabbitMqSink, s3::S3Sink},
+    sinks::{
+        file::FileSink, kafka::KafkaSink, postgres::PostgresSink, rabbitmq::RabbitMqSink,
+        s3::S3Sink,
+    },
+    transform::ItemRecord,
 };
 
+/// What a sink receives from the indexer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SinkPayload {
+    /// [`Stash`]es with their nested items, see [`Sink::handle`]
+    #[default]
+    Stashes,
+    /// A flat [`ItemRecord`] per item, see [`Sink::handle_items`]
+    Items,
+}
+
+impl FromStr for SinkPayload {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stashes" => Ok(SinkPayload::Stashes),
+            "items" => Ok(SinkPayload::Items),
+            _ => Err(format!("expected stashes or items, got {s}")),
+        }
+    }
+}
+
 #[async_trait]
-pub trait Sink {
+pub trait Sink: Send {
     /// Handles processing a slice of [`Stash`].
     /// Each Sink implementation should handle errors internally, as the caller won't be able to handle it so we don't want
     /// to block on Sink-specific errors.
     /// TODO: make sure the usage of multiple sinks doesn't block each other.
     async fn handle(&mut self, payload: &[Stash]) -> Result<usize, Box<dyn std::error::Error>>;
 
+    /// Handles processing a slice of [`ItemRecord`], in place of [`Sink::handle`] for sinks
+    /// whose [`Sink::payload`] is [`SinkPayload::Items`].
+    async fn handle_items(
+        &mut self,
+        _payload: &[ItemRecord],
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        Err("This sink does not support item records".into())
+    }
+
+    fn payload(&self) -> SinkPayload {
+        SinkPayload::Stashes
+    }
+
     /// Sinks can be stateful and so want to be flushed upon graceful shutdown.
     async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>>;
 }
@@ -30,10 +71,33 @@ pub async fn setup_sinks(
     }
 
     if let Some(config) = config.s3 {
-        let s3_sink = S3Sink::connect(&config.bucket_name, &config.region).await?;
+        let s3_sink = S3Sink::connect(
+            &config.bucket_name,
+            &config.region,
+            config.format,
+            config.schema,
+        )
+        .await?;
         sinks.push(Box::new(s3_sink));
         tracing::info!("Configured S3 sink");
     }
 
+    if let Some(config) = config.postgres {
+        let postgres_sink = PostgresSink::connect(&config).await?;
+        sinks.push(Box::new(postgres_sink));
+        tracing::info!("Configured PostgreSQL sink");
+    }
+
+    if let Some(conf) = config.kafka {
+        let kafka_sink = KafkaSink::connect(conf)?;
+        sinks.push(Box::new(kafka_sink));
+        tracing::info!("Configured Kafka sink");
+    }
+
+    if let Some(config) = config.file {
+        sinks.push(Box::new(FileSink::new(&config.dir, config.schema)));
+        tracing::info!("Configured file sink in {}", config.dir);
+    }
+
     Ok(sinks)
 }
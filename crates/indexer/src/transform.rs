@@ -0,0 +1,199 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize, Serializer};
+use stash_api::{
+    common::{
+        realm::Realm,
+        schema::{CamelCase, SerializeCamelCase},
+        stash::Stash,
+    },
+    poe_api::poe_stash_api::protocol::Item,
+};
+use trade_common::note_parser::PriceParser;
+
+/// A single item, joined with the stash it is listed in and its parsed price.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemRecord {
+    pub stash_id: String,
+    pub account_name: Option<String>,
+    pub stash: Option<String>,
+    pub league: Option<String>,
+    pub realm: Realm,
+    pub change_id: String,
+    pub created_at: NaiveDateTime,
+    pub price: Option<ItemPrice>,
+    pub item: Item,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CamelCaseItemRecord<'a> {
+    stash_id: &'a str,
+    account_name: &'a Option<String>,
+    stash: &'a Option<String>,
+    league: &'a Option<String>,
+    realm: Realm,
+    change_id: &'a str,
+    created_at: &'a NaiveDateTime,
+    price: &'a Option<ItemPrice>,
+    item: CamelCase<'a, Item>,
+}
+
+impl SerializeCamelCase for ItemRecord {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let ItemRecord {
+            stash_id,
+            account_name,
+            stash,
+            league,
+            realm,
+            change_id,
+            created_at,
+            price,
+            item,
+        } = self;
+
+        CamelCaseItemRecord {
+            stash_id,
+            account_name,
+            stash,
+            league,
+            realm: *realm,
+            change_id,
+            created_at,
+            price,
+            item: CamelCase(item),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// An asking price, ie. `~b/o 12/19 chaos` for `12/19` of a `chaos` orb
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemPrice {
+    pub ratio: f32,
+    pub currency: String,
+}
+
+/// Flattens [`Stash`]es into [`ItemRecord`]s for the sinks that opted into them, see
+/// [`SinkPayload`](crate::sinks::sink::SinkPayload).
+#[derive(Default)]
+pub struct ItemTransform {
+    parser: PriceParser,
+}
+
+impl ItemTransform {
+    pub fn item_records(&self, stashes: &[Stash]) -> Vec<ItemRecord> {
+        stashes
+            .iter()
+            .flat_map(|stash| {
+                // A price in the name of a stash applies to all of its items without their own price
+                let stash_price = stash.stash.as_deref().and_then(|name| self.price(name));
+
+                stash.items.iter().map(move |item| ItemRecord {
+                    stash_id: stash.id.clone(),
+                    account_name: stash.account_name.clone(),
+                    stash: stash.stash.clone(),
+                    league: stash.league.clone(),
+                    realm: stash.realm,
+                    change_id: stash.change_id.clone(),
+                    created_at: stash.created_at,
+                    price: item
+                        .note
+                        .as_deref()
+                        .and_then(|note| self.price(note))
+                        .or_else(|| stash_price.clone()),
+                    item: item.clone(),
+                })
+            })
+            .collect()
+    }
+
+    fn price(&self, note: &str) -> Option<ItemPrice> {
+        self.parser.parse_price(note).map(|price| ItemPrice {
+            ratio: price.ratio,
+            currency: price.item.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use stash_api::common::{realm::Realm, stash::Stash};
+
+    use super::{ItemPrice, ItemTransform};
+
+    fn stash(name: Option<&str>, notes: &[Option<&str>]) -> Stash {
+        Stash {
+            id: "a".into(),
+            public: true,
+            account_name: Some("foo".into()),
+            stash: name.map(Into::into),
+            stash_type: "PremiumStash".into(),
+            items: notes
+                .iter()
+                .map(|note| {
+                    serde_json::from_value(json!({
+                        "verified": false, "w": 1, "h": 1, "icon": "", "name": "",
+                        "typeLine": "Orb", "baseType": "Orb", "identified": true, "ilvl": 0,
+                        "note": note,
+                    }))
+                    .unwrap()
+                })
+                .collect(),
+            rejected_items: vec![],
+            league: Some("Standard".into()),
+            realm: Realm::Poe2,
+            created_at: Default::default(),
+            change_id: "1".into(),
+            next_change_id: "2".into(),
+            extra: Default::default(),
+        }
+    }
+
+    fn price(ratio: f32, currency: &str) -> Option<ItemPrice> {
+        Some(ItemPrice {
+            ratio,
+            currency: currency.into(),
+        })
+    }
+
+    #[test]
+    fn test_item_records() {
+        let stashes = [
+            stash(None, &[Some("~b/o 3 chaos")]),
+            stash(Some("Dump"), &[]),
+            stash(Some("Dump"), &[None, Some("Legacy")]),
+        ];
+
+        let records = ItemTransform::default().item_records(&stashes);
+        assert_eq!(records.len(), 3);
+        for record in &records {
+            assert_eq!(record.stash_id, "a");
+            assert_eq!(record.account_name.as_deref(), Some("foo"));
+            assert_eq!(record.league.as_deref(), Some("Standard"));
+            assert_eq!(record.realm, Realm::Poe2);
+            assert_eq!(record.change_id, "1");
+        }
+        assert_eq!(records[0].stash, None);
+        assert_eq!(records[0].price, price(3.0, "chaos"));
+        assert_eq!(records[1].stash.as_deref(), Some("Dump"));
+        assert_eq!(records[1].item, stashes[2].items[0]);
+        assert_eq!(records[1].price, None);
+        assert_eq!(records[2].price, None);
+    }
+
+    #[test]
+    fn test_item_records_price_precedence() {
+        let stashes = [stash(
+            Some("~price 1/2 divine"),
+            &[None, Some("~b/o 5 chaos"), Some("Legacy")],
+        )];
+
+        let records = ItemTransform::default().item_records(&stashes);
+        // The note of an item overrides the price in the name of its stash
+        assert_eq!(records[0].price, price(0.5, "divine"));
+        assert_eq!(records[1].price, price(5.0, "chaos"));
+        assert_eq!(records[2].price, price(0.5, "divine"));
+    }
+}
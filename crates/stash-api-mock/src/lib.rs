@@ -0,0 +1,9 @@
+//! A mock of the Public Stash Tab API river, GGG's OAuth token endpoint and poe.ninja,
+//! to run the ingest pipeline end to end without talking to GGG.
+mod rate_limit;
+mod script;
+mod server;
+
+pub use rate_limit::RateLimitRule;
+pub use script::{Script, Step};
+pub use server::{MockConfig, MockServer};
@@ -0,0 +1,57 @@
+extern crate dotenv;
+
+use std::net::SocketAddr;
+
+use stash_api_mock::{MockConfig, MockServer, RateLimitRule, Script};
+use tracing::info;
+use trade_common::telemetry::setup_telemetry;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv::dotenv().ok();
+
+    setup_telemetry("stash-api-mock").expect("Telemetry setup");
+
+    let port = read_from_env("MOCK_PORT").unwrap_or(8080);
+    let script = match std::env::var("MOCK_FIXTURES_DIR") {
+        Ok(dir) => Script::from_dir(dir)?,
+        Err(_) => Script::synthetic(
+            "0-0-0-0-0",
+            read_from_env("MOCK_SYNTHETIC_PAGES").unwrap_or(100),
+            read_from_env("MOCK_STASHES_PER_PAGE").unwrap_or(50),
+        ),
+    };
+    let rate_limit_rules = std::env::var("MOCK_RATE_LIMIT_RULES")
+        .map(|rules| {
+            rules
+                .split(',')
+                .map(|r| r.parse::<RateLimitRule>())
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .unwrap_or(Ok(vec![]))?;
+    let credentials = match (
+        std::env::var("POE_CLIENT_ID"),
+        std::env::var("POE_CLIENT_SECRET"),
+    ) {
+        (Ok(id), Ok(secret)) => Some((id, secret)),
+        _ => None,
+    };
+
+    let config = MockConfig {
+        script,
+        credentials,
+        rate_limit_rules,
+        token_lifetime: None,
+    };
+    let server = MockServer::bind(SocketAddr::from(([0, 0, 0, 0], port)), config).await?;
+    info!("Serving mock API at {}", server.url());
+
+    tokio::signal::ctrl_c().await?;
+    info!("Shutting down");
+
+    Ok(())
+}
+
+fn read_from_env<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// A single rate limit rule in the format GGG uses for its `X-Rate-Limit-*` headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitRule {
+    /// Maximum number of requests within `period`
+    pub max_hits: u32,
+    pub period: Duration,
+    /// How long a client is restricted once it exceeded `max_hits`
+    pub restriction: Duration,
+}
+
+impl FromStr for RateLimitRule {
+    type Err = String;
+
+    /// Parses `max_hits:period:restriction`, ie. `45:60:60`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(':')
+            .map(|p| p.trim().parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid rate limit rule {s}: {e}"))?;
+
+        match parts[..] {
+            [max_hits, period, restriction] => Ok(Self {
+                max_hits: max_hits as u32,
+                period: Duration::from_secs(period),
+                restriction: Duration::from_secs(restriction),
+            }),
+            _ => Err(format!("Invalid rate limit rule {s}")),
+        }
+    }
+}
+
+/// Tracks requests of the single client the mock expects to talk to.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimitState {
+    rules: Vec<RateLimitRule>,
+    hits: VecDeque<Instant>,
+    restricted_until: Option<Instant>,
+}
+
+impl RateLimitState {
+    pub(crate) fn new(rules: Vec<RateLimitRule>) -> Self {
+        Self {
+            rules,
+            ..Default::default()
+        }
+    }
+
+    /// Registers a request and returns the remaining restriction if the client is restricted.
+    pub(crate) fn hit(&mut self, now: Instant) -> Option<Duration> {
+        if let Some(until) = self.restricted_until {
+            if until > now {
+                return Some(until - now);
+            }
+            self.restricted_until = None;
+        }
+
+        let longest_period = self.rules.iter().map(|r| r.period).max()?;
+        while matches!(self.hits.front(), Some(hit) if now.duration_since(*hit) > longest_period) {
+            self.hits.pop_front();
+        }
+        self.hits.push_back(now);
+
+        for rule in &self.rules {
+            if self.hits_within(rule.period, now) > rule.max_hits {
+                let until = now + rule.restriction;
+                self.restricted_until = Some(self.restricted_until.map_or(until, |u| u.max(until)));
+            }
+        }
+
+        self.restricted_until.map(|until| until - now)
+    }
+
+    fn hits_within(&self, period: Duration, now: Instant) -> u32 {
+        self.hits
+            .iter()
+            .filter(|hit| now.duration_since(**hit) <= period)
+            .count() as u32
+    }
+
+    /// Renders the `X-Rate-Limit-*` headers for the current state
+    pub(crate) fn headers(&self, now: Instant) -> Vec<(&'static str, String)> {
+        if self.rules.is_empty() {
+            return vec![];
+        }
+
+        let active = self
+            .restricted_until
+            .filter(|until| *until > now)
+            .map(|until| (until - now).as_secs())
+            .unwrap_or(0);
+
+        let rules = self
+            .rules
+            .iter()
+            .map(|r| {
+                format!(
+                    "{}:{}:{}",
+                    r.max_hits,
+                    r.period.as_secs(),
+                    r.restriction.as_secs()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let state = self
+            .rules
+            .iter()
+            .map(|r| {
+                format!(
+                    "{}:{}:{}",
+                    self.hits_within(r.period, now),
+                    r.period.as_secs(),
+                    active
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        vec![
+            (
+                "x-rate-limit-policy",
+                "public-stash-tabs-request-limit".into(),
+            ),
+            ("x-rate-limit-rules", "Ip".into()),
+            ("x-rate-limit-ip", rules),
+            ("x-rate-limit-ip-state", state),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{RateLimitRule, RateLimitState};
+
+    #[test]
+    fn test_restricts_after_max_hits() {
+        let rule = "2:10:30".parse::<RateLimitRule>().unwrap();
+        let mut state = RateLimitState::new(vec![rule]);
+        let now = Instant::now();
+
+        assert_eq!(state.hit(now), None);
+        assert_eq!(state.hit(now), None);
+        assert_eq!(state.hit(now), Some(Duration::from_secs(30)));
+
+        let headers = state.headers(now);
+        assert!(headers.contains(&("x-rate-limit-ip", "2:10:30".into())));
+        assert!(headers.contains(&("x-rate-limit-ip-state", "3:10:30".into())));
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Read,
+    path::Path,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use serde_json::json;
+
+/// A single scripted response of the mocked river.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Serves the given body as-is with status code 200
+    Page(Bytes),
+    /// Serves an empty page that points back to the requested change id, which is what
+    /// the river does once a consumer caught up with the latest data
+    Empty,
+    /// Responds with status code 429 and a `Retry-After` header
+    RateLimited(Duration),
+    /// Responds with the given 5xx status code
+    ServerError(u16),
+    /// Responds with status code 401, as if the access token was revoked
+    Unauthorized,
+}
+
+/// A scripted chain of responses, keyed by change id.
+///
+/// Every request for a change id pops the next [`Step`] scripted for it. The last step of
+/// every change id is sticky and served for all subsequent requests. Change ids without
+/// any scripted steps are answered with [`Step::Empty`], just like the end of the river.
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    head: Option<String>,
+    steps: HashMap<String, VecDeque<Step>>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedules `step` as the next response for `change_id`.
+    ///
+    /// The first change id that is scripted becomes the head of the river that the mocked
+    /// poe.ninja endpoint reports.
+    pub fn respond(mut self, change_id: impl Into<String>, step: Step) -> Self {
+        let change_id = change_id.into();
+        self.head.get_or_insert_with(|| change_id.clone());
+        self.steps.entry(change_id).or_default().push_back(step);
+        self
+    }
+
+    /// Schedules `step` as the very first response for `change_id`, before everything
+    /// else that is already scripted for it, ie. to inject errors into a generated chain.
+    pub fn inject(mut self, change_id: impl Into<String>, step: Step) -> Self {
+        let change_id = change_id.into();
+        self.head.get_or_insert_with(|| change_id.clone());
+        self.steps.entry(change_id).or_default().push_front(step);
+        self
+    }
+
+    /// Schedules a page with the given raw body for `change_id`.
+    pub fn page(self, change_id: impl Into<String>, body: impl Into<Bytes>) -> Self {
+        self.respond(change_id, Step::Page(body.into()))
+    }
+
+    /// Generates a chain of `pages` pages starting at `start`, each holding
+    /// `stashes_per_page` public stashes with a single item.
+    pub fn synthetic(start: &str, pages: usize, stashes_per_page: usize) -> Self {
+        let mut script = Self::new();
+        let mut change_id = start.to_string();
+
+        for page in 0..pages {
+            let next_change_id = advance_change_id(&change_id, stashes_per_page as u64);
+            let stashes = (0..stashes_per_page)
+                .map(|n| synthetic_stash(page * stashes_per_page + n))
+                .collect::<Vec<_>>();
+            let body = json!({
+                "next_change_id": next_change_id,
+                "stashes": stashes,
+            });
+            script = script.page(change_id, body.to_string());
+            change_id = next_change_id;
+        }
+
+        script
+    }
+
+    /// Loads recorded pages from a directory of `{change_id}.json` files, or of the
+    /// `{change_id}.json.gz` files that the indexer records.
+    ///
+    /// The head of the resulting chain is the only page that no other page refers to as
+    /// its `next_change_id`.
+    pub fn from_dir(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        #[derive(Deserialize)]
+        struct PageHeader {
+            next_change_id: String,
+        }
+
+        let mut pages = HashMap::new();
+        for entry in std::fs::read_dir(path)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let (change_id, body) = if let Some(change_id) = name.strip_suffix(".json.gz") {
+                let mut body = vec![];
+                GzDecoder::new(std::fs::File::open(&path)?).read_to_end(&mut body)?;
+                (change_id.to_string(), body)
+            } else if let Some(change_id) = name.strip_suffix(".json") {
+                (change_id.to_string(), std::fs::read(&path)?)
+            } else {
+                continue;
+            };
+            let header = serde_json::from_slice::<PageHeader>(&body)?;
+            pages.insert(change_id, (header.next_change_id, body));
+        }
+
+        let head = pages
+            .keys()
+            .find(|id| pages.values().all(|(next, _)| next != *id))
+            .cloned();
+
+        let mut script = Self {
+            head,
+            ..Default::default()
+        };
+        for (change_id, (_, body)) in pages {
+            script
+                .steps
+                .entry(change_id)
+                .or_default()
+                .push_back(Step::Page(body.into()));
+        }
+
+        Ok(script)
+    }
+
+    /// The change id that the mocked poe.ninja endpoint reports as latest.
+    pub fn head(&self) -> Option<&str> {
+        self.head.as_deref()
+    }
+
+    pub(crate) fn next_step(&mut self, change_id: &str) -> Step {
+        match self.steps.get_mut(change_id) {
+            Some(steps) if steps.len() > 1 => steps.pop_front().unwrap(),
+            Some(steps) if !steps.is_empty() => steps[0].clone(),
+            _ => Step::Empty,
+        }
+    }
+}
+
+pub(crate) fn empty_page(change_id: &str) -> String {
+    json!({ "next_change_id": change_id, "stashes": [] }).to_string()
+}
+
+/// Increments every shard of a change id by `by`
+fn advance_change_id(change_id: &str, by: u64) -> String {
+    change_id
+        .split('-')
+        .map(|shard| (shard.parse::<u64>().unwrap_or_default() + by).to_string())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn synthetic_stash(n: usize) -> serde_json::Value {
+    json!({
+        "id": format!("{n:064x}"),
+        "public": true,
+        "accountName": format!("account-{n}"),
+        "stash": "~price 1 chaos",
+        "stashType": "PremiumStash",
+        "league": "Standard",
+        "items": [{
+            "verified": false,
+            "w": 1,
+            "h": 1,
+            "icon": "https://web.poecdn.com/image/Art/2DItems/Currency/CurrencyRerollRare.png",
+            "stackSize": 1,
+            "maxStackSize": 40,
+            "league": "Standard",
+            "id": format!("{:064x}", n + 1_000_000),
+            "name": "",
+            "typeLine": "Chaos Orb",
+            "baseType": "Chaos Orb",
+            "identified": true,
+            "ilvl": 0,
+            "frameType": 5,
+            "x": 0,
+            "y": 0,
+            "inventoryId": "Stash1",
+        }],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Script, Step};
+
+    #[test]
+    fn test_last_step_is_sticky() {
+        let mut script = Script::new()
+            .respond("1-1", Step::ServerError(503))
+            .page("1-1", "page");
+
+        assert_eq!(script.next_step("1-1"), Step::ServerError(503));
+        assert_eq!(script.next_step("1-1"), Step::Page("page".into()));
+        assert_eq!(script.next_step("1-1"), Step::Page("page".into()));
+        assert_eq!(script.next_step("2-2"), Step::Empty);
+    }
+
+    #[test]
+    fn test_synthetic_chain() {
+        let mut script = Script::synthetic("0-0", 2, 3);
+
+        assert_eq!(script.head(), Some("0-0"));
+        let Step::Page(first) = script.next_step("0-0") else {
+            panic!("Expected a page");
+        };
+        let first = serde_json::from_slice::<serde_json::Value>(&first).unwrap();
+        assert_eq!(first["next_change_id"], "3-3");
+        assert_eq!(first["stashes"].as_array().unwrap().len(), 3);
+        assert!(matches!(script.next_step("3-3"), Step::Page(_)));
+        assert_eq!(script.next_step("6-6"), Step::Empty);
+    }
+}
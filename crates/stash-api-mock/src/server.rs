@@ -0,0 +1,684 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+use crate::{
+    rate_limit::{RateLimitRule, RateLimitState},
+    script::{empty_page, Script, Step},
+};
+
+/// Configures the behaviour of a [`MockServer`].
+#[derive(Debug, Clone, Default)]
+pub struct MockConfig {
+    pub script: Script,
+    /// Only hand out access tokens for this client id and secret, if set
+    pub credentials: Option<(String, String)>,
+    /// Rate limit rules that are announced and enforced on the river endpoint
+    pub rate_limit_rules: Vec<RateLimitRule>,
+    /// How long issued access tokens are valid, forever if unset
+    pub token_lifetime: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct MockState {
+    config: MockConfig,
+    rate_limit: RateLimitState,
+    tokens: HashMap<String, Instant>,
+    requested_change_ids: Vec<String>,
+    issued_tokens: usize,
+}
+
+type SharedState = Arc<Mutex<MockState>>;
+
+/// An HTTP server that mocks the Public Stash Tab API river, GGG's OAuth token endpoint and
+/// poe.ninja's latest change id endpoint on a single address.
+///
+/// The server shuts down once it is dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    state: SharedState,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Starts the server on a random local port
+    pub async fn start(config: MockConfig) -> std::io::Result<Self> {
+        Self::bind(([127, 0, 0, 1], 0).into(), config).await
+    }
+
+    pub async fn bind(addr: SocketAddr, config: MockConfig) -> std::io::Result<Self> {
+        let state = Arc::new(Mutex::new(MockState {
+            rate_limit: RateLimitState::new(config.rate_limit_rules.clone()),
+            config,
+            tokens: Default::default(),
+            requested_change_ids: vec![],
+            issued_tokens: 0,
+        }));
+
+        let app = Router::new()
+            .route("/public-stash-tabs", get(handle_river))
+            .route("/public-stash-tabs/{realm}", get(handle_river))
+            .route("/oauth/token", post(handle_oauth))
+            .route("/api/Data/GetStats", get(handle_poe_ninja))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        info!("Starting mock API: {addr}");
+
+        let handle = tokio::spawn(async move {
+            let _ = axum::serve(listener, app.into_make_service()).await;
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    /// The base URL to configure the indexer with for all endpoints
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// All change ids that were requested from the river so far, in order
+    pub fn requested_change_ids(&self) -> Vec<String> {
+        self.state.lock().unwrap().requested_change_ids.clone()
+    }
+
+    /// How many access tokens were issued so far
+    pub fn issued_tokens(&self) -> usize {
+        self.state.lock().unwrap().issued_tokens
+    }
+
+    /// Invalidates all access tokens that were issued so far
+    pub fn revoke_tokens(&self) {
+        self.state.lock().unwrap().tokens.clear();
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_river(
+    State(state): State<SharedState>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let now = Instant::now();
+    let mut state = state.lock().unwrap();
+    let change_id = query.get("id").cloned().unwrap_or_default();
+    debug!("Mock river request for {change_id}");
+    state.requested_change_ids.push(change_id.clone());
+
+    if !state.is_authorized(&headers, now) {
+        return with_headers(StatusCode::UNAUTHORIZED, vec![], "").into_response();
+    }
+
+    if let Some(restriction) = state.rate_limit.hit(now) {
+        return rate_limited(restriction, state.rate_limit.headers(now));
+    }
+    let rate_limit_headers = state.rate_limit.headers(now);
+
+    match state.config.script.next_step(&change_id) {
+        Step::Page(body) => with_headers(StatusCode::OK, rate_limit_headers, body).into_response(),
+        Step::Empty => {
+            with_headers(StatusCode::OK, rate_limit_headers, empty_page(&change_id)).into_response()
+        }
+        Step::RateLimited(retry_after) => rate_limited(retry_after, rate_limit_headers),
+        Step::ServerError(status) => {
+            let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            with_headers(status, rate_limit_headers, "").into_response()
+        }
+        Step::Unauthorized => {
+            with_headers(StatusCode::UNAUTHORIZED, rate_limit_headers, "").into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OAuthForm {
+    client_id: String,
+    client_secret: String,
+    grant_type: String,
+    scope: String,
+}
+
+async fn handle_oauth(State(state): State<SharedState>, Form(form): Form<OAuthForm>) -> Response {
+    let mut state = state.lock().unwrap();
+
+    let valid_client = match &state.config.credentials {
+        Some((id, secret)) => id == &form.client_id && secret == &form.client_secret,
+        None => true,
+    };
+    if !valid_client || form.grant_type != "client_credentials" {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "invalid_client",
+                "error_description": "Client authentication failed",
+            })),
+        )
+            .into_response();
+    }
+
+    state.issued_tokens += 1;
+    let token = format!("mock-token-{}", state.issued_tokens);
+    state.tokens.insert(token.clone(), Instant::now());
+
+    Json(json!({
+        "access_token": token,
+        "expires_in": state.config.token_lifetime.map(|l| l.as_secs()),
+        "token_type": "bearer",
+        "scope": form.scope,
+        "username": form.client_id,
+        "sub": form.client_id,
+    }))
+    .into_response()
+}
+
+async fn handle_poe_ninja(State(state): State<SharedState>) -> Response {
+    let state = state.lock().unwrap();
+    match state.config.script.head() {
+        Some(head) => Json(json!({ "next_change_id": head })).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+impl MockState {
+    fn is_authorized(&self, headers: &HeaderMap, now: Instant) -> bool {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "));
+
+        match token.and_then(|t| self.tokens.get(t)) {
+            Some(issued_at) => match self.config.token_lifetime {
+                Some(lifetime) => now.duration_since(*issued_at) < lifetime,
+                None => true,
+            },
+            None => false,
+        }
+    }
+}
+
+fn rate_limited(retry_after: Duration, mut headers: Vec<(&'static str, String)>) -> Response {
+    // Round up so clients never retry too early
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    headers.push(("retry-after", seconds.to_string()));
+    with_headers(StatusCode::TOO_MANY_REQUESTS, headers, "").into_response()
+}
+
+fn with_headers(
+    status: StatusCode,
+    headers: Vec<(&'static str, String)>,
+    body: impl IntoResponse,
+) -> impl IntoResponse {
+    let mut map = HeaderMap::new();
+    map.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    for (name, value) in headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            map.insert(name, value);
+        }
+    }
+    (status, map, body)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use stash_api::{
+        common::{realm::Realm, stash::Stash, StashApiError},
+        r#async::indexer::{Indexer, IndexerBuilder, IndexerMessage},
+    };
+    use trade_common::{secret::SecretString, telemetry::generate_http_client};
+
+    use super::{MockConfig, MockServer};
+    use crate::script::{Script, Step};
+
+    fn indexer(server: &MockServer) -> IndexerBuilder {
+        Indexer::builder(
+            "client-id".into(),
+            SecretString::new("client-secret".into()),
+            SecretString::new("developer@example.com".into()),
+        )
+        .stash_api_base_url(server.url())
+        .oauth_base_url(server.url())
+        .poe_ninja_base_url(server.url())
+        .http_client(generate_http_client(None))
+    }
+
+    #[tokio::test]
+    async fn test_indexer_follows_scripted_chain() {
+        let server = MockServer::start(MockConfig {
+            script: Script::synthetic("0-0-0", 3, 2),
+            credentials: Some(("client-id".into(), "client-secret".into())),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let (_, mut rx) = indexer(&server).build().start_with_latest().await.unwrap();
+
+        let mut change_ids = vec![];
+        while change_ids.len() < 3 {
+            if let Some(IndexerMessage::Tick {
+                change_id, stashes, ..
+            }) = rx.recv().await
+            {
+                assert_eq!(stashes.len(), 2);
+                change_ids.push(change_id.to_string());
+            }
+        }
+        change_ids.sort();
+
+        assert_eq!(change_ids, vec!["0-0-0", "2-2-2", "4-4-4"]);
+        assert_eq!(server.issued_tokens(), 1);
+        assert!(server.requested_change_ids().starts_with(&[
+            "0-0-0".into(),
+            "2-2-2".into(),
+            "4-4-4".into()
+        ]));
+    }
+
+    #[tokio::test]
+    async fn test_indexer_honors_rate_limits() {
+        let server = MockServer::start(MockConfig {
+            script: Script::synthetic("0-0-0", 3, 2)
+                .inject("2-2-2", Step::RateLimited(Duration::from_secs(1))),
+            rate_limit_rules: vec!["20:1:1".parse().unwrap()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let (_, mut rx) = indexer(&server).build().start_with_latest().await.unwrap();
+
+        let mut rate_limits = vec![];
+        let mut ticks = 0;
+        while ticks < 3 {
+            match rx.recv().await.unwrap() {
+                IndexerMessage::RateLimited(timer) => rate_limits.push(timer),
+                IndexerMessage::Tick { .. } => ticks += 1,
+                _ => {}
+            }
+        }
+
+        assert_eq!(rate_limits, vec![Duration::from_secs(1)]);
+        assert_eq!(
+            server
+                .requested_change_ids()
+                .iter()
+                .filter(|id| *id == "2-2-2")
+                .count(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_indexer_refreshes_rejected_tokens() {
+        let server = MockServer::start(MockConfig {
+            script: Script::synthetic("0-0-0", 3, 2),
+            rate_limit_rules: vec!["20:1:1".parse().unwrap()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let (_, mut rx) = indexer(&server).build().start_with_latest().await.unwrap();
+        server.revoke_tokens();
+
+        let mut ticks = 0;
+        while ticks < 3 {
+            if let IndexerMessage::Tick { .. } = rx.recv().await.unwrap() {
+                ticks += 1;
+            }
+        }
+
+        assert_eq!(server.issued_tokens(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_indexer_refreshes_expiring_tokens() {
+        let server = MockServer::start(MockConfig {
+            script: Script::synthetic("0-0-0", 30, 1),
+            rate_limit_rules: vec!["20:1:1".parse().unwrap()],
+            token_lifetime: Some(Duration::from_secs(1)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let (_, mut rx) = indexer(&server).build().start_with_latest().await.unwrap();
+
+        let mut ticks = 0;
+        while ticks < 30 {
+            if let IndexerMessage::Tick { .. } = rx.recv().await.unwrap() {
+                ticks += 1;
+            }
+        }
+
+        assert!(server.issued_tokens() > 1);
+        assert!(server.requested_change_ids()[..30]
+            .iter()
+            .enumerate()
+            .all(|(n, id)| id == &format!("{n}-{n}-{n}")));
+    }
+
+    #[tokio::test]
+    async fn test_indexer_rejects_invalid_credentials() {
+        let server = MockServer::start(MockConfig {
+            script: Script::synthetic("0-0-0", 1, 1),
+            credentials: Some(("client-id".into(), "another-secret".into())),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let error = indexer(&server)
+            .build()
+            .start_at_change_id("0-0-0".parse().unwrap())
+            .await
+            .unwrap_err();
+
+        assert!(error.is_permanent());
+    }
+
+    #[tokio::test]
+    async fn test_indexer_reports_malformed_pages() {
+        let script = Script::synthetic("0-0-0", 1, 1);
+        let server = MockServer::start(MockConfig {
+            script: script.inject("0-0-0", Step::Page(r#"{"next_change_id": "1-1-1""#.into())),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let (_, mut rx) = indexer(&server).build().start_with_latest().await.unwrap();
+
+        let error = match rx.recv().await.unwrap() {
+            IndexerMessage::Error(e) => e,
+            msg => panic!("Expected an error, got {msg:?}"),
+        };
+        assert!(matches!(*error, StashApiError::Decode { .. }));
+        assert!(!error.is_fatal());
+        assert_eq!(error.change_id().unwrap().to_string(), "0-0-0");
+
+        match rx.recv().await.unwrap() {
+            IndexerMessage::Tick { change_id, .. } => assert_eq!(change_id.to_string(), "0-0-0"),
+            msg => panic!("Expected a tick, got {msg:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_playback_reproduces_malformed_pages() {
+        let script = Script::synthetic("0-0-0", 1, 1);
+        let server = MockServer::start(MockConfig {
+            script: script.inject("0-0-0", Step::Page(r#"{"next_change_id": "1-1-1""#.into())),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let recorder = indexer(&server).record_to(dir.path()).build();
+        let (handle, mut rx) = recorder.start_with_latest().await.unwrap();
+        while !matches!(rx.recv().await.unwrap(), IndexerMessage::Tick { .. }) {}
+        handle.stop();
+        while !matches!(rx.recv().await.unwrap(), IndexerMessage::Stop) {}
+
+        // The broken body was recorded first, so it is played back instead of the retry
+        let playback = indexer(&server).playback_from(dir.path()).build();
+        let (handle, mut rx) = playback
+            .start_at_change_id("0-0-0".parse().unwrap())
+            .await
+            .unwrap();
+
+        match rx.recv().await.unwrap() {
+            IndexerMessage::Error(e) => {
+                assert!(matches!(*e, StashApiError::Decode { .. }));
+                assert_eq!(e.change_id().unwrap().to_string(), "0-0-0");
+            }
+            msg => panic!("Expected an error, got {msg:?}"),
+        }
+        handle.stop();
+        assert!(matches!(rx.recv().await.unwrap(), IndexerMessage::Stop));
+    }
+
+    #[tokio::test]
+    async fn test_serve_recorded_river() {
+        let server = MockServer::start(MockConfig {
+            script: Script::synthetic("0-0-0", 3, 2),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let recorder = indexer(&server).record_to(dir.path()).build();
+        let (handle, mut rx) = recorder.start_with_latest().await.unwrap();
+        // Stashes are only compared by their contents, as they are stamped when indexed
+        let contents = |stashes: Vec<Stash>| {
+            stashes
+                .into_iter()
+                .map(|stash| (stash.id, stash.items))
+                .collect::<Vec<_>>()
+        };
+        let mut recorded = vec![];
+        while recorded.len() < 3 {
+            if let IndexerMessage::Tick { stashes, .. } = rx.recv().await.unwrap() {
+                recorded.push(contents(stashes));
+            }
+        }
+        handle.stop();
+        while !matches!(rx.recv().await.unwrap(), IndexerMessage::Stop) {}
+
+        // The recording is served as it is, starting at its first page
+        let server = MockServer::start(MockConfig {
+            script: Script::from_dir(dir.path()).unwrap(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let (_, mut rx) = indexer(&server).build().start_with_latest().await.unwrap();
+        for stashes in recorded {
+            match rx.recv().await.unwrap() {
+                IndexerMessage::Tick {
+                    stashes: served, ..
+                } => assert_eq!(contents(served), stashes),
+                msg => panic!("Expected a tick, got {msg:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_indexer_quarantines_broken_stashes() {
+        let page = r#"{"next_change_id": "1-1-1", "stashes": [{"id": 1}, {"id": "a",
+            "public": true, "accountName": null, "stash": null, "stashType": "PremiumStash",
+            "league": null, "items": []}]}"#;
+        let server = MockServer::start(MockConfig {
+            script: Script::new().page("0-0-0", page),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let (_, mut rx) = indexer(&server)
+            .decode_retries(0)
+            .dead_letter_to(dir.path())
+            .build()
+            .start_with_latest()
+            .await
+            .unwrap();
+
+        match rx.recv().await.unwrap() {
+            IndexerMessage::Error(e) => match &*e {
+                StashApiError::Quarantined {
+                    change_id,
+                    skipped_stashes,
+                    dead_letter,
+                    ..
+                } => {
+                    assert_eq!(change_id.to_string(), "0-0-0");
+                    assert_eq!(*skipped_stashes, 1);
+                    assert!(std::path::Path::new(dead_letter.as_ref().unwrap()).exists());
+                }
+                e => panic!("Expected a quarantined page, got {e:?}"),
+            },
+            msg => panic!("Expected an error, got {msg:?}"),
+        }
+
+        match rx.recv().await.unwrap() {
+            IndexerMessage::Tick {
+                stashes,
+                next_change_id,
+                ..
+            } => {
+                assert_eq!(stashes.len(), 1);
+                assert_eq!(next_change_id.to_string(), "1-1-1");
+            }
+            msg => panic!("Expected a tick, got {msg:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_indexer_follows_other_realms() {
+        let server = MockServer::start(MockConfig {
+            script: Script::synthetic("0-0-0", 1, 2),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let poe2 = indexer(&server).realm(Realm::Poe2).build();
+
+        // poe.ninja only knows about PC
+        assert!(poe2.start_with_latest().await.is_err());
+
+        let (_, mut rx) = poe2
+            .start_at_change_id("0-0-0".parse().unwrap())
+            .await
+            .unwrap();
+        match rx.recv().await.unwrap() {
+            IndexerMessage::Tick { stashes, .. } => {
+                assert_eq!(stashes.len(), 2);
+                assert!(stashes.iter().all(|s| s.realm == Realm::Poe2));
+            }
+            msg => panic!("Expected a tick, got {msg:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_consumer_throttles_fetching() {
+        let server = MockServer::start(MockConfig {
+            script: Script::synthetic("0-0-0", 20, 1),
+            rate_limit_rules: vec!["100:1:1".parse().unwrap()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let indexer = indexer(&server).buffer_size(2).build();
+        let (handle, mut rx) = indexer.start_with_latest().await.unwrap();
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        // Two pages hold a slot of the buffer and one more was handed over to the receiver
+        assert_eq!(handle.buffered(), 2);
+        assert!(server.requested_change_ids().len() <= 3);
+
+        for n in 0..20 {
+            match rx.recv().await.unwrap() {
+                IndexerMessage::Tick { change_id, .. } => {
+                    assert_eq!(change_id.to_string(), format!("{n}-{n}-{n}"))
+                }
+                msg => panic!("Expected a tick, got {msg:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_drains_in_flight_pages() {
+        let server = MockServer::start(MockConfig {
+            script: Script::synthetic("0-0-0", 50, 1),
+            rate_limit_rules: vec!["100:1:1".parse().unwrap()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let (handle, mut rx) = indexer(&server).build().start_with_latest().await.unwrap();
+
+        let mut change_ids = vec![];
+        while let Some(msg) = rx.recv().await {
+            match msg {
+                IndexerMessage::Tick { change_id, .. } => {
+                    change_ids.push(change_id.to_string());
+                    if change_ids.len() == 3 {
+                        handle.stop();
+                    }
+                }
+                IndexerMessage::Stop => break,
+                msg => panic!("Unexpected message {msg:?}"),
+            }
+        }
+
+        assert!(rx.recv().await.is_none());
+        handle.join().await;
+        assert!(change_ids.len() < 50);
+        assert!(change_ids
+            .iter()
+            .enumerate()
+            .all(|(n, id)| id == &format!("{n}-{n}-{n}")));
+    }
+
+    #[tokio::test]
+    async fn test_pause_holds_back_fetching() {
+        let server = MockServer::start(MockConfig {
+            script: Script::synthetic("0-0-0", 50, 1),
+            rate_limit_rules: vec!["100:1:1".parse().unwrap()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let (handle, mut rx) = indexer(&server).build().start_with_latest().await.unwrap();
+
+        assert!(matches!(rx.recv().await, Some(IndexerMessage::Tick { .. })));
+        handle.pause();
+        // Let in-flight pages settle
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let requested = server.requested_change_ids().len();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(server.requested_change_ids().len(), requested);
+
+        handle.resume();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(server.requested_change_ids().len() > requested);
+    }
+}
@@ -1,25 +1,59 @@
+use std::future::Future;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::{sync::Arc, time::Duration};
 
+use bytes::Bytes;
+
 use chrono::Utc;
-use tokio::sync::mpsc::{channel, Receiver, Sender};
-use tokio::sync::RwLock;
-use tracing::{debug, error, error_span, info, trace, trace_span};
+use reqwest::StatusCode;
+use tokio::sync::mpsc::{channel, unbounded_channel, Receiver, UnboundedSender};
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, error_span, info, trace, trace_span, warn};
 use trade_common::secret::SecretString;
 use trade_common::telemetry::generate_http_client;
-use trade_common::{ClientWithMiddleware, RateLimiter};
+use trade_common::ClientWithMiddleware;
 
-use crate::common::parse::parse_change_id_from_bytes;
+use super::sequencer::{sequence, Sequenced};
+use crate::common::page_parser::PageParser;
+use crate::common::poe_ninja_client::{PoeNinjaClient, DEFAULT_POE_NINJA_BASE_URL};
+use crate::common::realm::Realm;
+use crate::common::recording::Recording;
 use crate::common::stash::Stash;
-use crate::common::ChangeId;
-use crate::poe_api::auth::{get_oauth_token, user_agent, OAuthResponse};
-use crate::poe_api::poe_stash_api::protocol::PublicStashTabResponse;
+use crate::common::{ChangeId, StashApiError};
+use crate::poe_api::auth::{user_agent, OAuthCredentials, OAuthError, DEFAULT_OAUTH_BASE_URL};
+use crate::poe_api::rate_limit::{
+    parse_retry_after, AdaptiveRateLimiter, RateLimitPolicy, DEFAULT_RATE_LIMIT_TIMER,
+};
+
+pub const DEFAULT_STASH_API_BASE_URL: &str = "https://api.pathofexile.com";
+
+/// How often we request the river until GGG tells us its actual rate limit policy
+const INITIAL_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait before retrying a request that failed with a 5xx status code or did
+/// not reach the API at all
+const SERVER_ERROR_BACKOFF: Duration = Duration::from_secs(10);
+/// How many pages may be fetched ahead of the consumer by default
+pub const DEFAULT_BUFFER_SIZE: usize = 100;
+/// How often a page that fails to deserialize is retried by default before it gets quarantined
+pub const DEFAULT_DECODE_RETRIES: u32 = 3;
 
 #[derive(Debug)]
 pub struct Indexer {
     pub(crate) client_id: String,
     pub(crate) client_secret: SecretString,
     pub(crate) developer_mail: SecretString,
+    pub(crate) stash_api_base_url: String,
+    pub(crate) oauth_base_url: String,
+    pub(crate) poe_ninja_base_url: String,
+    pub(crate) http_client: Option<ClientWithMiddleware>,
+    pub(crate) buffer_size: usize,
+    pub(crate) recording: Option<Recording>,
+    pub(crate) playback: Option<Recording>,
+    pub(crate) decode_retries: u32,
+    pub(crate) dead_letter: Option<Recording>,
+    pub(crate) realm: Realm,
 }
 
 impl Indexer {
@@ -28,217 +62,776 @@ impl Indexer {
         client_secret: SecretString,
         developer_mail: SecretString,
     ) -> Self {
-        Self {
-            client_id,
-            client_secret,
-            developer_mail,
-        }
+        Self::builder(client_id, client_secret, developer_mail).build()
     }
 
-    /// Start the indexer with a given change_id
-    pub async fn start_at_change_id(&self, change_id: ChangeId) -> Receiver<IndexerMessage> {
-        // Workaround to not have to use [tracing::instrument]
-        trace_span!("start_at_change_id", change_id = change_id.inner.as_str());
-
-        info!("Starting at change id: {}", change_id);
+    pub fn builder(
+        client_id: String,
+        client_secret: SecretString,
+        developer_mail: SecretString,
+    ) -> IndexerBuilder {
+        IndexerBuilder::new(client_id, client_secret, developer_mail)
+    }
 
-        let credentials =
-            get_oauth_token(&self.client_id, &self.client_secret, &self.developer_mail)
-                .await
-                .expect("Fetch OAuth credentials");
+    /// Start the indexer with the latest change id that poe.ninja knows about.
+    ///
+    /// poe.ninja only tracks the [`Realm::Pc`] river, other realms have to be started with
+    /// [`start_at_change_id`](Indexer::start_at_change_id).
+    pub async fn start_with_latest(
+        &self,
+    ) -> Result<(IndexerHandle, Receiver<IndexerMessage>), Box<dyn std::error::Error>> {
+        if self.realm != Realm::Pc {
+            return Err(format!(
+                "poe.ninja does not know the latest change id of {}",
+                self.realm
+            )
+            .into());
+        }
 
-        let rate_limiter = RateLimiter::builder()
-            .initial(0)
-            .max(1)
-            .refill(1)
-            .interval(Duration::from_secs(1))
-            .build();
+        let poe_ninja = PoeNinjaClient::new(
+            self.poe_ninja_base_url.clone(),
+            self.http_client
+                .clone()
+                .unwrap_or_else(|| generate_http_client(None)),
+        );
+        let change_id = poe_ninja.fetch_latest_change_id().await?;
+        Ok(self.start_at_change_id(change_id).await?)
+    }
 
-        let client = Arc::new(generate_http_client(Some(rate_limiter)));
+    /// Start the indexer with a given change_id
+    ///
+    /// Returns an [`IndexerHandle`] to control the running indexer and the receiver of all
+    /// its messages. Fails if no OAuth access token can be fetched with the configured
+    /// credentials.
+    pub async fn start_at_change_id(
+        &self,
+        change_id: ChangeId,
+    ) -> Result<(IndexerHandle, Receiver<IndexerMessage>), OAuthError> {
+        // Workaround to not have to use [tracing::instrument]
+        trace_span!("start_at_change_id", change_id = %change_id);
 
-        let (tx, rx) = channel(100);
+        info!("Starting {} at change id: {}", self.realm, change_id);
 
-        schedule_job(
-            tx,
-            change_id,
+        let client = self
+            .http_client
+            .clone()
+            .unwrap_or_else(|| generate_http_client(None));
+        let credentials = OAuthCredentials::new(
+            client.clone(),
+            self.oauth_base_url.clone(),
             self.client_id.clone(),
             self.client_secret.clone(),
             self.developer_mail.clone(),
-            Arc::new(RwLock::new(Some(credentials))),
-            client,
+            self.realm.oauth_scope().into(),
         );
-        rx
+        // Fail early if the credentials are invalid
+        if self.playback.is_none() {
+            credentials.access_token().await?;
+        }
+
+        // Pages are buffered while they hold a slot of `in_flight`, so the channel only
+        // hands them over one at a time
+        let (tx, rx) = channel(1);
+        let (job_tx, job_rx) = unbounded_channel();
+        let sequencer = tokio::spawn(sequence(change_id.clone(), job_rx, tx));
+
+        let context = JobContext {
+            client_id: self.client_id.clone(),
+            developer_mail: self.developer_mail.clone(),
+            stash_api_base_url: self.stash_api_base_url.clone(),
+            credentials,
+            client,
+            rate_limiter: AdaptiveRateLimiter::new(INITIAL_REQUEST_INTERVAL),
+            in_flight: Arc::new(Semaphore::new(self.buffer_size)),
+            buffer_size: self.buffer_size,
+            control: watch::Sender::new(Control::Running),
+            recording: self.recording.clone(),
+            playback: self.playback.clone(),
+            decode_retries: self.decode_retries,
+            dead_letter: self.dead_letter.clone(),
+            realm: self.realm,
+        };
+        let context = Arc::new(context);
+
+        schedule_job(job_tx, Job::new(change_id), context.clone());
+        Ok((IndexerHandle { context, sequencer }, rx))
+    }
+}
+
+/// Controls a running [`Indexer`].
+///
+/// Dropping the handle does not stop the indexer, it keeps running until [`stop`] is called
+/// or the receiver of its messages is dropped.
+///
+/// [`stop`]: IndexerHandle::stop
+#[derive(Debug)]
+pub struct IndexerHandle {
+    context: Arc<JobContext>,
+    sequencer: JoinHandle<()>,
+}
+
+impl IndexerHandle {
+    /// Stops fetching new pages.
+    ///
+    /// Pages that are already being downloaded are still delivered, followed by
+    /// [`IndexerMessage::Stop`] once all of them are drained.
+    pub fn stop(&self) {
+        self.context.control.send_replace(Control::Stopped);
+    }
+
+    /// Holds back fetching new pages until [`resume`](IndexerHandle::resume) is called
+    pub fn pause(&self) {
+        self.context
+            .control
+            .send_if_modified(|control| match control {
+                Control::Running => {
+                    *control = Control::Paused;
+                    true
+                }
+                _ => false,
+            });
+    }
+
+    /// Continues fetching after [`pause`](IndexerHandle::pause)
+    pub fn resume(&self) {
+        self.context
+            .control
+            .send_if_modified(|control| match control {
+                Control::Paused => {
+                    *control = Control::Running;
+                    true
+                }
+                _ => false,
+            });
+    }
+
+    /// The number of pages that are being fetched or wait to be handed to the receiver,
+    /// including ticks that are held back until the pages before them arrived.
+    ///
+    /// At most the buffer size, see [`IndexerBuilder::buffer_size`].
+    pub fn buffered(&self) -> usize {
+        self.context.buffer_size - self.context.in_flight.available_permits()
+    }
+
+    /// Resolves once all in-flight pages are drained and handed to the receiver
+    pub async fn join(self) {
+        let _ = self.sequencer.await;
     }
 }
 
-fn schedule_job(
-    tx: Sender<IndexerMessage>,
-    next_change_id: ChangeId,
+/// Configures where an [`Indexer`] fetches its data from.
+///
+/// All base URLs default to the official endpoints, but can be pointed to any server
+/// that speaks the same protocol, ie. a local mock of the Public Stash Tab API river
+/// or a caching proxy.
+#[derive(Debug)]
+pub struct IndexerBuilder {
     client_id: String,
     client_secret: SecretString,
     developer_mail: SecretString,
-    config: Arc<RwLock<Option<OAuthResponse>>>,
-    client: Arc<ClientWithMiddleware>,
-) {
-    tokio::spawn(process(
-        next_change_id,
-        tx,
-        client_id,
-        client_secret,
-        developer_mail,
-        config,
-        client,
-    ));
+    stash_api_base_url: String,
+    oauth_base_url: String,
+    poe_ninja_base_url: String,
+    http_client: Option<ClientWithMiddleware>,
+    buffer_size: usize,
+    recording: Option<Recording>,
+    playback: Option<Recording>,
+    decode_retries: u32,
+    dead_letter: Option<Recording>,
+    realm: Realm,
 }
 
-#[tracing::instrument(skip(tx, config))]
-async fn process(
-    change_id: ChangeId,
-    tx: Sender<IndexerMessage>,
+impl IndexerBuilder {
+    pub fn new(
+        client_id: String,
+        client_secret: SecretString,
+        developer_mail: SecretString,
+    ) -> Self {
+        Self {
+            client_id,
+            client_secret,
+            developer_mail,
+            stash_api_base_url: DEFAULT_STASH_API_BASE_URL.into(),
+            oauth_base_url: DEFAULT_OAUTH_BASE_URL.into(),
+            poe_ninja_base_url: DEFAULT_POE_NINJA_BASE_URL.into(),
+            http_client: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            recording: None,
+            playback: None,
+            decode_retries: DEFAULT_DECODE_RETRIES,
+            dead_letter: None,
+            realm: Realm::default(),
+        }
+    }
+
+    /// Base URL of the Public Stash Tab API, defaults to [`DEFAULT_STASH_API_BASE_URL`]
+    pub fn stash_api_base_url(mut self, url: impl Into<String>) -> Self {
+        self.stash_api_base_url = url.into();
+        self
+    }
+
+    /// Base URL of the OAuth token endpoint, defaults to [`DEFAULT_OAUTH_BASE_URL`]
+    pub fn oauth_base_url(mut self, url: impl Into<String>) -> Self {
+        self.oauth_base_url = url.into();
+        self
+    }
+
+    /// Base URL of poe.ninja, defaults to [`DEFAULT_POE_NINJA_BASE_URL`]
+    pub fn poe_ninja_base_url(mut self, url: impl Into<String>) -> Self {
+        self.poe_ninja_base_url = url.into();
+        self
+    }
+
+    /// Use the given HTTP client for all requests.
+    ///
+    /// Requests to the river are always paced according to the rate limit policy that
+    /// the API announces, so the client itself does not need to be rate limited.
+    pub fn http_client(mut self, client: ClientWithMiddleware) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// How many pages may be fetched ahead of the consumer, defaults to
+    /// [`DEFAULT_BUFFER_SIZE`].
+    ///
+    /// A page takes up a slot from the moment it is requested until it is handed to the
+    /// receiver, so a slow consumer throttles fetching. On top of that, the receiver holds at
+    /// most one message that it did not take yet.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Records the raw body of every non-empty page into `dir`, see [`Recording`]
+    pub fn record_to(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.recording = Some(Recording::new(dir));
+        self
+    }
+
+    /// Reads pages from a [`Recording`] in `dir` instead of requesting them from the API.
+    ///
+    /// Recorded bodies go through the same parsing as live responses, which makes it easy
+    /// to reproduce deserialization issues. The indexer stops at the first change id that
+    /// was not recorded.
+    pub fn playback_from(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.playback = Some(Recording::new(dir));
+        self
+    }
+
+    /// How often a page that fails to deserialize is retried, defaults to
+    /// [`DEFAULT_DECODE_RETRIES`].
+    ///
+    /// Afterwards the page is quarantined: stashes that fail to deserialize are skipped and
+    /// the indexer moves on to its `next_change_id`, reporting
+    /// [`StashApiError::Quarantined`].
+    pub fn decode_retries(mut self, retries: u32) -> Self {
+        self.decode_retries = retries;
+        self
+    }
+
+    /// Writes the raw body of quarantined pages into `dir`, see [`Recording`]
+    pub fn dead_letter_to(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dead_letter = Some(Recording::new(dir));
+        self
+    }
+
+    /// Which river to follow, defaults to [`Realm::Pc`]
+    pub fn realm(mut self, realm: Realm) -> Self {
+        self.realm = realm;
+        self
+    }
+
+    pub fn build(self) -> Indexer {
+        Indexer {
+            client_id: self.client_id,
+            client_secret: self.client_secret,
+            developer_mail: self.developer_mail,
+            stash_api_base_url: self.stash_api_base_url.trim_end_matches('/').to_string(),
+            oauth_base_url: self.oauth_base_url,
+            poe_ninja_base_url: self.poe_ninja_base_url,
+            http_client: self.http_client,
+            buffer_size: self.buffer_size,
+            // Recording a playback would only copy the recording
+            recording: self.recording.filter(|_| self.playback.is_none()),
+            playback: self.playback,
+            decode_retries: self.decode_retries,
+            dead_letter: self.dead_letter,
+            realm: self.realm,
+        }
+    }
+}
+
+/// State that is shared between all fetch jobs of a running [`Indexer`]
+#[derive(Debug)]
+struct JobContext {
     client_id: String,
-    client_secret: SecretString,
     developer_mail: SecretString,
-    config: Arc<RwLock<Option<OAuthResponse>>>,
-    client: Arc<ClientWithMiddleware>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    stash_api_base_url: String,
+    credentials: OAuthCredentials,
+    client: ClientWithMiddleware,
+    rate_limiter: AdaptiveRateLimiter,
+    /// Bounds the number of pages that are fetched but not yet delivered
+    in_flight: Arc<Semaphore>,
+    /// The number of permits of `in_flight`
+    buffer_size: usize,
+    control: watch::Sender<Control>,
+    recording: Option<Recording>,
+    playback: Option<Recording>,
+    decode_retries: u32,
+    dead_letter: Option<Recording>,
+    realm: Realm,
+}
+
+impl JobContext {
+    /// Waits while the indexer is paused, returns `false` once it is stopped
+    async fn wait_until_running(&self) -> bool {
+        let mut control = self.control.subscribe();
+        let running = match control.wait_for(|c| *c != Control::Paused).await {
+            Ok(c) => *c == Control::Running,
+            Err(_) => false,
+        };
+        running
+    }
+
+    /// Runs `future` to completion, unless the indexer gets stopped meanwhile
+    async fn unless_stopped<F: Future>(&self, future: F) -> Option<F::Output> {
+        let mut control = self.control.subscribe();
+        tokio::select! {
+            output = future => Some(output),
+            _ = control.wait_for(|c| *c == Control::Stopped) => None,
+        }
+    }
+
+    /// Sleeps for `duration`, returns `false` if the indexer got stopped meanwhile
+    async fn sleep(&self, duration: Duration) -> bool {
+        self.unless_stopped(tokio::time::sleep(duration))
+            .await
+            .is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Running,
+    Paused,
+    Stopped,
+}
+
+/// A single attempt at fetching the page of a change id
+#[derive(Debug)]
+struct Job {
+    change_id: ChangeId,
+    /// Whether a previous attempt already scheduled the next page
+    next_scheduled: bool,
+    /// How often previous attempts failed to deserialize the page
+    decode_failures: u32,
+    /// The buffer slot of this page, which is kept across retries so that later pages
+    /// cannot starve it
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Job {
+    fn new(change_id: ChangeId) -> Self {
+        Self {
+            change_id,
+            next_scheduled: false,
+            decode_failures: 0,
+            permit: None,
+        }
+    }
+}
+
+fn schedule_job(tx: UnboundedSender<Sequenced>, job: Job, context: Arc<JobContext>) {
+    tokio::spawn(process(job, tx, context));
+}
+
+#[tracing::instrument(skip_all, fields(change_id = %job.change_id))]
+async fn process(mut job: Job, tx: UnboundedSender<Sequenced>, context: Arc<JobContext>) {
     // check if stopping
-    if tx.is_closed() {
-        return Ok(());
+    if tx.is_closed() || !context.wait_until_running().await {
+        return;
+    }
+
+    // Wait until the consumer caught up far enough to take another page
+    if job.permit.is_none() {
+        let permit = context
+            .unless_stopped(context.in_flight.clone().acquire_owned())
+            .await;
+        match permit {
+            Some(Ok(permit)) => job.permit = Some(permit),
+            _ => return,
+        }
+    }
+    let change_id = job.change_id.clone();
+
+    if let Some(playback) = &context.playback {
+        let body = match playback.read(&change_id).await {
+            Ok(body) => body,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("Reached the end of the recording at {}", change_id);
+                return;
+            }
+            Err(e) => {
+                report(
+                    &tx,
+                    StashApiError::Archive {
+                        location: playback.path(&change_id).display().to_string(),
+                        reason: e.to_string(),
+                    },
+                );
+                return;
+            }
+        };
+        read_page(job, Body::Recorded(Some(body)), tx, context).await;
+        return;
     }
 
     let url = format!(
-        "https://api.pathofexile.com/public-stash-tabs?id={}",
+        "{}{}?id={}",
+        context.stash_api_base_url,
+        context.realm.river_path(),
         &change_id
     );
+    let access_token = match context.credentials.access_token().await {
+        Ok(access_token) => access_token,
+        Err(e) if e.is_permanent() => {
+            error!("Stopping due to invalid OAuth credentials: {}", e);
+            report(&tx, StashApiError::Auth(e));
+            return;
+        }
+        Err(e) => {
+            warn!(
+                "Rescheduling in {}s due to failed OAuth token refresh: {}",
+                SERVER_ERROR_BACKOFF.as_secs(),
+                e
+            );
+            report(&tx, StashApiError::Auth(e));
+            if context.sleep(SERVER_ERROR_BACKOFF).await {
+                schedule_job(tx, job, context);
+            }
+            return;
+        }
+    };
+
+    if context
+        .unless_stopped(context.rate_limiter.acquire())
+        .await
+        .is_none()
+    {
+        return;
+    }
     debug!("Requesting {}", url);
 
-    let response = client
+    let response = context
+        .client
         .get(url)
         .header("Accept", "application/json")
         .header(
             "User-Agent",
-            user_agent(&client_id, developer_mail.expose()),
-        )
-        .header(
-            "Authorization",
-            format!(
-                "Bearer {}",
-                config
-                    .read()
-                    .await
-                    .as_ref()
-                    .expect("OAuth credentials are set")
-                    .access_token
-            )
-            .as_str(),
+            user_agent(&context.client_id, context.developer_mail.expose()),
         )
+        .header("Authorization", format!("Bearer {access_token}").as_str())
         .send()
         .await;
 
-    let mut response = match response {
+    let response = match response {
         Err(e) => {
             error!("Error when fetching change_id {}: {:?}", change_id, e);
             error_span!("handle_fetch_error").in_scope(|| {
                 error!("Error response: {:?}", e);
                 error!(fetch_error = ?e);
-                schedule_job(
-                    tx,
-                    change_id,
-                    client_id,
-                    client_secret,
-                    developer_mail,
-                    config,
-                    client,
-                );
             });
-            return Ok(());
+            report(
+                &tx,
+                StashApiError::Network {
+                    change_id,
+                    source: e,
+                },
+            );
+            // Don't hammer the API while it is unreachable
+            if context.sleep(SERVER_ERROR_BACKOFF).await {
+                schedule_job(tx, job, context);
+            }
+            return;
         }
         Ok(data) => data,
     };
 
-    if !response.status().is_success() {
-        info!(
-            "Rescheduling in 60s due to HTTP response status code {}",
-            response.status().as_u16()
-        );
-        tokio::time::sleep(Duration::from_secs(60)).await;
-        schedule_job(
-            tx,
-            change_id,
-            client_id,
-            client_secret,
-            developer_mail,
-            config,
-            client,
-        );
-        return Ok(());
+    let rate_limit_policy = RateLimitPolicy::from_headers(response.headers());
+    if let Some(policy) = &rate_limit_policy {
+        context.rate_limiter.adapt(policy);
+    }
+
+    match response.status() {
+        status if status.is_success() => context.credentials.accept(),
+        StatusCode::TOO_MANY_REQUESTS => {
+            let timer = parse_retry_after(response.headers())
+                .or_else(|| rate_limit_policy.and_then(|p| p.active_restriction()))
+                .unwrap_or(DEFAULT_RATE_LIMIT_TIMER);
+            warn!(
+                "Rate limited for {}s when fetching change_id {}",
+                timer.as_secs(),
+                change_id
+            );
+            context.rate_limiter.restrict(timer);
+            let _ = tx.send(IndexerMessage::RateLimited(timer).into());
+            schedule_job(tx, job, context);
+            return;
+        }
+        StatusCode::UNAUTHORIZED => {
+            if let Err(e) = context.credentials.reject(&access_token).await {
+                error!("Stopping due to invalid OAuth credentials: {}", e);
+                report(&tx, StashApiError::Auth(e));
+                return;
+            }
+            warn!("Rescheduling with a new access token after it got rejected");
+            schedule_job(tx, job, context);
+            return;
+        }
+        status if status.is_server_error() => {
+            let backoff = parse_retry_after(response.headers()).unwrap_or(SERVER_ERROR_BACKOFF);
+            warn!(
+                "Rescheduling in {}s due to HTTP response status code {}",
+                backoff.as_secs(),
+                status.as_u16()
+            );
+            if context.sleep(backoff).await {
+                schedule_job(tx, job, context);
+            }
+            return;
+        }
+        status => {
+            info!(
+                "Rescheduling in 60s due to HTTP response status code {}",
+                status.as_u16()
+            );
+            if context.sleep(Duration::from_secs(60)).await {
+                schedule_job(tx, job, context);
+            }
+            return;
+        }
     }
 
-    let mut bytes = vec![];
-    let mut prefetch_done = false;
-    while let Some(chunk) = response.chunk().await? {
-        bytes.extend(chunk);
+    read_page(job, Body::Response(response), tx, context).await;
+}
+
+/// The body of a page, either streamed from the API or read from a [`Recording`]
+enum Body {
+    Response(reqwest::Response),
+    Recorded(Option<Vec<u8>>),
+}
 
-        if bytes.len() > 60 && !prefetch_done {
-            if seems_empty(&bytes) {
-                debug!("Rescheduling in 4s due to empty response");
-                tokio::time::sleep(Duration::from_secs(4)).await;
-                schedule_job(
-                    tx,
-                    change_id,
-                    client_id,
-                    client_secret,
-                    developer_mail,
-                    config,
-                    client,
+impl Body {
+    async fn chunk(&mut self) -> reqwest::Result<Option<Bytes>> {
+        match self {
+            Body::Response(response) => response.chunk().await,
+            Body::Recorded(body) => Ok(body.take().map(Bytes::from)),
+        }
+    }
+}
+
+/// Parses the body of a page and hands its stashes over to the sequencer
+async fn read_page(
+    mut job: Job,
+    mut body: Body,
+    tx: UnboundedSender<Sequenced>,
+    context: Arc<JobContext>,
+) {
+    let change_id = job.change_id.clone();
+    // Out of retries, so take whatever can be read from the page
+    let lenient = job.decode_failures >= context.decode_retries;
+    let mut parser = match lenient {
+        true => PageParser::lenient(),
+        false => PageParser::new(),
+    };
+    let mut changes = vec![];
+    let mut raw =
+        (context.recording.is_some() || (lenient && context.dead_letter.is_some())).then(Vec::new);
+    let mut decode_error = None;
+    loop {
+        let chunk = match body.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                warn!(
+                    "Rescheduling in {}s due to interrupted response: {:?}",
+                    SERVER_ERROR_BACKOFF.as_secs(),
+                    e
                 );
-                return Ok(());
-            } else {
-                let next_change_id = parse_change_id_from_bytes(&bytes).unwrap();
-                tracing::trace!(next_change_id = ?next_change_id);
-                prefetch_done = true;
-                schedule_job(
-                    tx.clone(),
-                    next_change_id,
-                    client_id.clone(),
-                    client_secret.clone(),
-                    developer_mail.clone(),
-                    config.clone(),
-                    client.clone(),
+                report(
+                    &tx,
+                    StashApiError::Network {
+                        change_id,
+                        source: e.into(),
+                    },
                 );
+                if context.sleep(SERVER_ERROR_BACKOFF).await {
+                    schedule_job(tx, job, context);
+                }
+                return;
+            }
+        };
+
+        if let Some(raw) = &mut raw {
+            raw.extend_from_slice(&chunk);
+        }
+        if decode_error.is_some() {
+            continue;
+        }
+
+        match parser.feed(&chunk) {
+            Ok(parsed) => changes.extend(parsed),
+            Err(e) => {
+                decode_error = Some(e);
+                // Keep reading the rest of the body if it gets recorded
+                if raw.is_some() {
+                    continue;
+                }
+                break;
+            }
+        }
+
+        // Schedule the next page as soon as we know it, unless we are caught up with the
+        // river and keep getting empty pages for the same change id
+        if !job.next_scheduled && parser.has_stashes() == Some(true) {
+            if let Some(Ok(next_change_id)) = parser.next_change_id().map(ChangeId::from_str) {
+                tracing::trace!(next_change_id = ?next_change_id);
+                job.next_scheduled = true;
+                schedule_job(tx.clone(), Job::new(next_change_id), context.clone());
+            }
+        }
+    }
+
+    let has_stashes = parser.has_stashes();
+    let skipped = parser.skipped().len();
+    let skip_reason = parser.skipped().first().map(ToString::to_string);
+    let known_next_change_id = parser.next_change_id().map(str::to_string);
+    let result = match decode_error {
+        Some(e) => Err(e),
+        None => parser.finish(),
+    };
+    let (next_change_id, quarantine) = match (result, known_next_change_id) {
+        (Ok(next_change_id), _) => (Ok(next_change_id), skip_reason),
+        // Give up on the rest of the page, as long as we know where the river continues
+        (Err(e), Some(next_change_id)) if lenient => (Ok(next_change_id), Some(e.to_string())),
+        (Err(e), _) => (Err(e), None),
+    };
+
+    let dead_letter = match (&quarantine, &context.dead_letter, &raw) {
+        (Some(_), Some(dead_letter), Some(raw)) => {
+            match dead_letter.write(&change_id, raw.clone()).await {
+                Ok(()) => Some(dead_letter.path(&change_id).display().to_string()),
+                Err(e) => {
+                    warn!(
+                        "Failed to write dead letter of change_id {}: {}",
+                        change_id, e
+                    );
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    // Empty pages are not worth recording, they look the same for every poll of the river
+    if let (Some(recording), Some(raw)) = (&context.recording, raw) {
+        if has_stashes != Some(false) || next_change_id.is_err() {
+            if let Err(e) = recording.write(&change_id, raw).await {
+                warn!("Failed to record change_id {}: {}", change_id, e);
             }
         }
     }
 
-    let deserialised = match serde_json::from_slice::<PublicStashTabResponse>(&bytes) {
-        Ok(deserialised) => deserialised,
+    let next_change_id = match next_change_id {
+        Ok(next_change_id) => next_change_id,
         Err(e) => {
             info!("Rescheduling in 5s due to deserialization issue {:?}", e);
-            tokio::time::sleep(Duration::from_secs(5)).await;
-            schedule_job(
-                tx,
-                change_id,
-                client_id,
-                client_secret,
-                developer_mail,
-                config,
-                client,
+            job.decode_failures += 1;
+            report(
+                &tx,
+                StashApiError::Decode {
+                    change_id,
+                    source: e,
+                },
             );
-            return Ok(());
+            if context.sleep(Duration::from_secs(5)).await {
+                schedule_job(tx, job, context);
+            }
+            return;
         }
     };
     debug!(
         "Read response {} with {} stashes",
-        deserialised.next_change_id,
-        deserialised.stashes.len()
+        next_change_id,
+        changes.len()
     );
-    trace!(number_stashes = ?deserialised.stashes.len());
+    trace!(number_stashes = ?changes.len());
+
+    if let Some(reason) = quarantine {
+        warn!(
+            "Quarantined change_id {} after {} failed attempts, skipping {} stashes",
+            change_id, job.decode_failures, skipped
+        );
+        report(
+            &tx,
+            StashApiError::Quarantined {
+                change_id: change_id.clone(),
+                skipped_stashes: skipped,
+                reason,
+                dead_letter,
+            },
+        );
+    }
+
+    if has_stashes == Some(false) {
+        debug!("Rescheduling in 4s due to empty response");
+        if context.sleep(Duration::from_secs(4)).await {
+            schedule_job(tx, job, context);
+        }
+        return;
+    }
+
+    let parsed_change_id = ChangeId::from_str(&next_change_id).map_err(|e| e.to_string());
+    let parsed_change_id = match parsed_change_id {
+        Ok(parsed_change_id) => parsed_change_id,
+        Err(reason) => {
+            report(&tx, StashApiError::Protocol { change_id, reason });
+            if context.sleep(Duration::from_secs(5)).await {
+                schedule_job(tx, job, context);
+            }
+            return;
+        }
+    };
+
+    // The river only ever moves forward, anything else hints at a problem on GGG's end
+    if parsed_change_id
+        .partial_cmp(&change_id)
+        .is_none_or(|ord| ord.is_lt())
+    {
+        report(
+            &tx,
+            StashApiError::Protocol {
+                change_id: change_id.clone(),
+                reason: format!("next change id {parsed_change_id} is not ahead"),
+            },
+        );
+    }
+
+    let realm = context.realm;
+    if !job.next_scheduled {
+        schedule_job(tx.clone(), Job::new(parsed_change_id.clone()), context);
+    }
+
+    let rejected_items = changes
+        .iter()
+        .map(|s| s.rejected_items.len())
+        .sum::<usize>();
+    if rejected_items > 0 {
+        debug!("Kept {} items that failed to deserialize", rejected_items);
+    }
 
-    let next_change_id =
-        ChangeId::from_str(&deserialised.next_change_id).expect("Invalid next_change_id");
     let now = Utc::now().naive_utc();
-    let stashes = deserialised
-        .stashes
+    let stashes = changes
         .into_iter()
         .map(|s| Stash {
             account_name: s.account_name,
@@ -246,30 +839,31 @@ async fn process(
             stash: s.stash,
             stash_type: s.stash_type,
             items: s.items,
+            rejected_items: s.rejected_items,
             public: s.public,
             league: s.league,
+            realm,
             created_at: now,
             change_id: change_id.to_string(),
-            next_change_id: deserialised.next_change_id.clone(),
+            next_change_id: next_change_id.clone(),
+            extra: s.extra,
         })
         .collect::<Vec<_>>();
 
-    tx.try_send(IndexerMessage::Tick {
-        stashes,
-        change_id,
-        next_change_id,
-        created_at: std::time::SystemTime::now(),
-    })
-    .expect("Sending IndexerMessage failed");
-
-    Ok(())
+    let _ = tx.send(Sequenced {
+        message: IndexerMessage::Tick {
+            stashes,
+            change_id,
+            next_change_id: parsed_change_id,
+            created_at: std::time::SystemTime::now(),
+        },
+        permit: job.permit,
+    });
 }
 
-fn seems_empty(bytes: &[u8]) -> bool {
-    match std::str::from_utf8(bytes) {
-        Ok(text) => text.contains("stashes:[]") || text.contains("\"stashes\":[]"),
-        Err(_) => false,
-    }
+/// Hands an error over to the consumer, who decides how to deal with it
+fn report(tx: &UnboundedSender<Sequenced>, error: StashApiError) {
+    let _ = tx.send(IndexerMessage::Error(Arc::new(error)).into());
 }
 
 #[derive(Debug, Clone)]
@@ -282,6 +876,12 @@ pub enum IndexerMessage {
         next_change_id: ChangeId,
         created_at: std::time::SystemTime,
     },
+    /// The API rate limited us for the given duration, the indexer resumes on its own
     RateLimited(Duration),
+    /// Something went wrong, the indexer keeps going unless the error
+    /// [`is_fatal`](StashApiError::is_fatal)
+    Error(Arc<StashApiError>),
+    /// The indexer stopped and all in-flight pages were delivered, this is always the last
+    /// message
     Stop,
 }
@@ -1 +1,3 @@
 pub mod indexer;
+pub mod replayer;
+mod sequencer;
@@ -0,0 +1,460 @@
+use std::{
+    error::Error,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use flate2::read::MultiGzDecoder;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tracing::{debug, info, warn};
+
+use super::indexer::{IndexerMessage, DEFAULT_BUFFER_SIZE};
+use crate::common::{stash::Stash, ChangeId, StashApiError};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// The time bucket format of archive keys, ie. `Standard/2024/01/31/23/59.json.gz`
+const TIME_BUCKET: &str = "%Y/%m/%d/%H/%M";
+
+/// Where archived stashes are read from.
+///
+/// Archives are gzipped JSON-lines files of [`Stash`]es in the layout that the S3 and file
+/// sinks of the indexer write: `{league}/{YYYY}/{MM}/{DD}/{HH}/{MM}.json.gz`
+#[derive(Debug, Clone)]
+pub enum ArchiveSource {
+    /// A local directory, ie. a downloaded copy of the bucket or the file sink's directory
+    Directory(PathBuf),
+    /// All keys below `prefix` in an S3 bucket
+    #[cfg(feature = "s3")]
+    S3 {
+        client: aws_sdk_s3::Client,
+        bucket: String,
+        prefix: String,
+    },
+}
+
+impl ArchiveSource {
+    /// Lists the keys of all archives, relative to the source
+    async fn list(&self) -> Result<Vec<String>, BoxError> {
+        match self {
+            ArchiveSource::Directory(root) => {
+                let root = root.clone();
+                tokio::task::spawn_blocking(move || {
+                    let mut keys = vec![];
+                    list_dir(&root, &root, &mut keys)?;
+                    Ok(keys)
+                })
+                .await?
+            }
+            #[cfg(feature = "s3")]
+            ArchiveSource::S3 {
+                client,
+                bucket,
+                prefix,
+            } => {
+                let mut keys = vec![];
+                let mut pages = client
+                    .list_objects_v2()
+                    .bucket(bucket)
+                    .prefix(prefix)
+                    .into_paginator()
+                    .send();
+                while let Some(page) = pages.next().await {
+                    keys.extend(
+                        page?
+                            .contents()
+                            .iter()
+                            .filter_map(|object| object.key())
+                            .map(|key| key.trim_start_matches(prefix.as_str()).to_string()),
+                    );
+                }
+                Ok(keys)
+            }
+        }
+    }
+
+    /// Reads the raw contents of an archive
+    async fn read(&self, key: &str) -> Result<Vec<u8>, BoxError> {
+        match self {
+            ArchiveSource::Directory(root) => Ok(tokio::fs::read(root.join(key)).await?),
+            #[cfg(feature = "s3")]
+            ArchiveSource::S3 {
+                client,
+                bucket,
+                prefix,
+            } => {
+                let object = client
+                    .get_object()
+                    .bucket(bucket)
+                    .key(format!("{prefix}{key}"))
+                    .send()
+                    .await?;
+                Ok(object.body.collect().await?.into_bytes().to_vec())
+            }
+        }
+    }
+}
+
+fn list_dir(root: &Path, dir: &Path, keys: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_dir(root, &path, keys)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let parts = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>();
+            keys.push(parts.join("/"));
+        }
+    }
+    Ok(())
+}
+
+/// A single archive, holding all stashes of a league that were indexed within a minute
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Archive {
+    key: String,
+    league: String,
+    bucket: NaiveDateTime,
+}
+
+impl Archive {
+    fn from_key(key: &str) -> Option<Self> {
+        let parts = key.strip_suffix(".json.gz")?.split('/').collect::<Vec<_>>();
+        let [.., league, year, month, day, hour, minute] = parts[..] else {
+            return None;
+        };
+        let bucket = NaiveDateTime::parse_from_str(
+            &format!("{year}/{month}/{day}/{hour}/{minute}"),
+            TIME_BUCKET,
+        )
+        .ok()?;
+
+        Some(Self {
+            key: key.to_string(),
+            league: league.to_string(),
+            bucket,
+        })
+    }
+}
+
+/// Replays archived stashes as if they came from a running [`Indexer`].
+///
+/// Stashes are grouped into [`IndexerMessage::Tick`]s by their change id and delivered in
+/// the order they were indexed, followed by [`IndexerMessage::Stop`]. Archives that cannot
+/// be read are reported as [`StashApiError::Archive`] and skipped.
+///
+/// [`Indexer`]: super::indexer::Indexer
+#[derive(Debug)]
+pub struct Replayer {
+    source: ArchiveSource,
+    from: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+    leagues: Option<Vec<String>>,
+    speed: Option<f64>,
+    buffer_size: usize,
+}
+
+impl Replayer {
+    pub fn new(source: ArchiveSource) -> Self {
+        Self {
+            source,
+            from: None,
+            until: None,
+            leagues: None,
+            speed: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    /// Replays archives from a local directory
+    pub fn from_dir(path: impl Into<PathBuf>) -> Self {
+        Self::new(ArchiveSource::Directory(path.into()))
+    }
+
+    /// Replays archives from an S3 bucket, ie. the one the indexer's S3 sink writes to
+    #[cfg(feature = "s3")]
+    pub fn from_s3(
+        client: aws_sdk_s3::Client,
+        bucket: impl Into<String>,
+        prefix: impl Into<String>,
+    ) -> Self {
+        Self::new(ArchiveSource::S3 {
+            client,
+            bucket: bucket.into(),
+            prefix: prefix.into(),
+        })
+    }
+
+    /// Only replays stashes that were indexed within `from..=until`, at minute precision
+    pub fn time_range(mut self, from: Option<NaiveDateTime>, until: Option<NaiveDateTime>) -> Self {
+        self.from = from;
+        self.until = until;
+        self
+    }
+
+    /// Only replays stashes of the given leagues
+    pub fn leagues(mut self, leagues: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.leagues = Some(leagues.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Replays with the given multiple of the original pace, ie. `1.0` for real time.
+    ///
+    /// By default, stashes are replayed as fast as the consumer takes them.
+    pub fn speed(mut self, multiplier: f64) -> Self {
+        self.speed = Some(multiplier).filter(|m| *m > 0.0);
+        self
+    }
+
+    /// How many messages may be buffered for the consumer, defaults to [`DEFAULT_BUFFER_SIZE`]
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Starts replaying, fails if the archives cannot be listed
+    pub async fn start(self) -> Result<Receiver<IndexerMessage>, BoxError> {
+        let mut archives = self
+            .source
+            .list()
+            .await?
+            .iter()
+            .filter_map(|key| Archive::from_key(key))
+            .filter(|a| self.from.is_none_or(|from| a.bucket >= truncate(from)))
+            .filter(|a| self.until.is_none_or(|until| a.bucket <= until))
+            .filter(|a| {
+                self.leagues
+                    .as_ref()
+                    .is_none_or(|leagues| leagues.contains(&a.league))
+            })
+            .collect::<Vec<_>>();
+        archives.sort_by(|a, b| a.bucket.cmp(&b.bucket).then(a.league.cmp(&b.league)));
+
+        info!("Replaying {} archives", archives.len());
+
+        let (tx, rx) = channel(self.buffer_size);
+        tokio::spawn(replay(self, archives, tx));
+        Ok(rx)
+    }
+}
+
+/// Cuts off everything below minutes, which is the precision of archive buckets
+fn truncate(datetime: NaiveDateTime) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(&datetime.format(TIME_BUCKET).to_string(), TIME_BUCKET)
+        .unwrap_or(datetime)
+}
+
+async fn replay(replayer: Replayer, archives: Vec<Archive>, tx: Sender<IndexerMessage>) {
+    let mut previous: Option<NaiveDateTime> = None;
+
+    for bucket in archives.chunk_by(|a, b| a.bucket == b.bucket) {
+        let mut stashes = vec![];
+        for archive in bucket {
+            match read_archive(&replayer.source, &archive.key).await {
+                Ok(read) => stashes.extend(read),
+                Err(e) => {
+                    warn!("Skipping archive {}: {}", archive.key, e);
+                    let error = StashApiError::Archive {
+                        location: archive.key.clone(),
+                        reason: e.to_string(),
+                    };
+                    if tx
+                        .send(IndexerMessage::Error(Arc::new(error)))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        }
+
+        stashes.retain(|s| {
+            replayer.from.is_none_or(|from| s.created_at >= from)
+                && replayer.until.is_none_or(|until| s.created_at <= until)
+        });
+        stashes.sort_by_key(|s| s.created_at);
+
+        let mut stashes = stashes.into_iter().peekable();
+        while let Some(first) = stashes.next() {
+            let created_at = first.created_at;
+            let mut tick = vec![first];
+            while let Some(stash) = stashes.next_if(|s| s.change_id == tick[0].change_id) {
+                tick.push(stash);
+            }
+
+            if let (Some(speed), Some(previous)) = (replayer.speed, previous) {
+                if let Ok(delay) = (created_at - previous).to_std() {
+                    tokio::time::sleep(delay.div_f64(speed)).await;
+                }
+            }
+            previous = Some(created_at);
+
+            let message = match to_tick(tick) {
+                Ok(message) => message,
+                Err(error) => IndexerMessage::Error(Arc::new(error)),
+            };
+            if tx.send(message).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    debug!("Replayed all archives");
+    let _ = tx.send(IndexerMessage::Stop).await;
+}
+
+async fn read_archive(source: &ArchiveSource, key: &str) -> Result<Vec<Stash>, BoxError> {
+    let bytes = source.read(key).await?;
+    tokio::task::spawn_blocking(move || {
+        let mut stashes = vec![];
+        for line in BufReader::new(MultiGzDecoder::new(bytes.as_slice())).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                stashes.push(serde_json::from_str::<Stash>(&line)?);
+            }
+        }
+        Ok(stashes)
+    })
+    .await?
+}
+
+fn to_tick(stashes: Vec<Stash>) -> Result<IndexerMessage, StashApiError> {
+    let first = &stashes[0];
+    let invalid = |reason: Box<dyn Error>| StashApiError::Archive {
+        location: first.change_id.clone(),
+        reason: reason.to_string(),
+    };
+    let change_id = ChangeId::from_str(&first.change_id).map_err(invalid)?;
+    let next_change_id = ChangeId::from_str(&first.next_change_id).map_err(invalid)?;
+    let created_at = Utc.from_utc_datetime(&first.created_at).into();
+
+    Ok(IndexerMessage::Tick {
+        stashes,
+        change_id,
+        next_change_id,
+        created_at,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use chrono::NaiveDateTime;
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::{Archive, Replayer};
+    use crate::{common::stash::Stash, r#async::indexer::IndexerMessage};
+
+    fn stash(id: &str, league: &str, change_id: &str, created_at: &str) -> Stash {
+        Stash {
+            id: id.into(),
+            public: true,
+            account_name: Some("account".into()),
+            stash: Some("~price 1 chaos".into()),
+            stash_type: "PremiumStash".into(),
+            items: vec![],
+            rejected_items: vec![],
+            league: Some(league.into()),
+            realm: Default::default(),
+            created_at: NaiveDateTime::parse_from_str(created_at, "%Y-%m-%d %H:%M:%S").unwrap(),
+            change_id: change_id.into(),
+            next_change_id: format!("{change_id}0"),
+            extra: Default::default(),
+        }
+    }
+
+    fn write_archive(root: &std::path::Path, key: &str, stashes: &[Stash]) {
+        let path = root.join(key);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        for stash in stashes {
+            writeln!(encoder, "{}", serde_json::to_string(stash).unwrap()).unwrap();
+        }
+        std::fs::write(path, encoder.finish().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_archive_from_key() {
+        let archive = Archive::from_key("Standard/2024/01/31/23/59.json.gz").unwrap();
+
+        assert_eq!(archive.league, "Standard");
+        assert_eq!(
+            archive.bucket.to_string(),
+            "2024-01-31 23:59:00".to_string()
+        );
+        assert_eq!(Archive::from_key("Standard/2024/01/31.json.gz"), None);
+        assert_eq!(Archive::from_key("Standard/2024/01/31/23/59.json"), None);
+    }
+
+    #[tokio::test]
+    async fn test_replay_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        write_archive(
+            dir.path(),
+            "Standard/2024/01/31/23/59.json.gz",
+            &[
+                stash("a", "Standard", "1-1", "2024-01-31 23:59:01"),
+                stash("c", "Standard", "2-2", "2024-01-31 23:59:02"),
+            ],
+        );
+        write_archive(
+            dir.path(),
+            "Hardcore/2024/01/31/23/59.json.gz",
+            &[stash("b", "Hardcore", "1-1", "2024-01-31 23:59:01")],
+        );
+        write_archive(
+            dir.path(),
+            "Standard/2024/02/01/00/00.json.gz",
+            &[stash("d", "Standard", "3-3", "2024-02-01 00:00:01")],
+        );
+        write_archive(
+            dir.path(),
+            "Ruthless/2024/01/31/23/59.json.gz",
+            &[stash("e", "Ruthless", "1-1", "2024-01-31 23:59:01")],
+        );
+        std::fs::write(
+            dir.path().join("Standard/2024/01/31/23/58.json.gz"),
+            "broken",
+        )
+        .unwrap();
+
+        let mut rx = Replayer::from_dir(dir.path())
+            .leagues(["Standard", "Hardcore"])
+            .start()
+            .await
+            .unwrap();
+
+        let mut ticks = vec![];
+        let mut errors = 0;
+        while let Some(msg) = rx.recv().await {
+            match msg {
+                IndexerMessage::Tick {
+                    change_id, stashes, ..
+                } => ticks.push((
+                    change_id.to_string(),
+                    stashes.into_iter().map(|s| s.id).collect::<Vec<_>>(),
+                )),
+                IndexerMessage::Error(_) => errors += 1,
+                IndexerMessage::Stop => break,
+                msg => panic!("Unexpected message {msg:?}"),
+            }
+        }
+
+        assert_eq!(errors, 1);
+        assert_eq!(
+            ticks,
+            vec![
+                ("1-1".to_string(), vec!["b".to_string(), "a".to_string()]),
+                ("2-2".to_string(), vec!["c".to_string()]),
+                ("3-3".to_string(), vec!["d".to_string()]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use tokio::sync::{
+    mpsc::{Sender, UnboundedReceiver},
+    OwnedSemaphorePermit,
+};
+
+use crate::common::ChangeId;
+
+use super::indexer::IndexerMessage;
+
+/// A message on its way from a fetch job to the consumer
+#[derive(Debug)]
+pub(crate) struct Sequenced {
+    pub(crate) message: IndexerMessage,
+    /// Keeps the slot of a page occupied until it is handed to the consumer
+    pub(crate) permit: Option<OwnedSemaphorePermit>,
+}
+
+impl From<IndexerMessage> for Sequenced {
+    fn from(message: IndexerMessage) -> Self {
+        Self {
+            message,
+            permit: None,
+        }
+    }
+}
+
+/// Forwards messages from the fetch jobs to the consumer.
+///
+/// Fetches of consecutive pages overlap, so their ticks can finish in any order. Ticks are
+/// held back until all ticks before them in the change id chain, starting at `start`, were
+/// released. All other messages are forwarded right away. Once all fetch jobs are gone,
+/// [`IndexerMessage::Stop`] is sent as the last message.
+pub(crate) async fn sequence(
+    start: ChangeId,
+    mut rx: UnboundedReceiver<Sequenced>,
+    tx: Sender<IndexerMessage>,
+) {
+    let mut expected = start;
+    let mut pending = HashMap::new();
+
+    while let Some(sequenced) = rx.recv().await {
+        match &sequenced.message {
+            IndexerMessage::Tick { change_id, .. } if *change_id != expected => {
+                pending.insert(change_id.clone(), sequenced);
+                continue;
+            }
+            IndexerMessage::Tick { .. } => {}
+            _ => {
+                if tx.send(sequenced.message).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        }
+
+        let mut next = Some(sequenced);
+        while let Some(Sequenced { message, permit }) = next {
+            if let IndexerMessage::Tick { next_change_id, .. } = &message {
+                expected = next_change_id.clone();
+            }
+            if tx.send(message).await.is_err() {
+                return;
+            }
+            drop(permit);
+            next = pending.remove(&expected);
+        }
+    }
+
+    if !pending.is_empty() {
+        tracing::warn!(
+            "Dropping {} ticks that arrived after a missing change id {}",
+            pending.len(),
+            expected
+        );
+    }
+
+    // All fetch jobs are done, so nothing else can arrive anymore
+    let _ = tx.send(IndexerMessage::Stop).await;
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+
+    use tokio::sync::mpsc::{channel, unbounded_channel};
+
+    use super::{sequence, Sequenced};
+    use crate::{common::ChangeId, r#async::indexer::IndexerMessage};
+
+    fn tick(change_id: &str, next_change_id: &str) -> Sequenced {
+        IndexerMessage::Tick {
+            stashes: vec![],
+            change_id: change_id.parse().unwrap(),
+            next_change_id: next_change_id.parse().unwrap(),
+            created_at: SystemTime::now(),
+        }
+        .into()
+    }
+
+    #[tokio::test]
+    async fn test_releases_ticks_in_chain_order() {
+        let (job_tx, job_rx) = unbounded_channel();
+        let (tx, mut rx) = channel(10);
+        let start = "1-1".parse::<ChangeId>().unwrap();
+
+        job_tx.send(tick("3-3", "4-4")).unwrap();
+        job_tx.send(tick("2-2", "3-3")).unwrap();
+        job_tx.send(IndexerMessage::Stop.into()).unwrap();
+        job_tx.send(tick("1-1", "2-2")).unwrap();
+        drop(job_tx);
+        sequence(start, job_rx, tx).await;
+
+        let mut order = vec![];
+        while let Some(msg) = rx.recv().await {
+            match msg {
+                IndexerMessage::Tick { change_id, .. } => order.push(change_id.to_string()),
+                _ => order.push("other".into()),
+            }
+        }
+
+        assert_eq!(order, vec!["other", "1-1", "2-2", "3-3", "other"]);
+    }
+}
@@ -1,13 +1,69 @@
-use std::fmt::Display;
+use std::{cmp::Ordering, fmt::Display};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Points to a page of the Public Stash Tab API river.
+///
+/// A change id consists of one counter per shard of the river, ie. `850662131-863318628`.
+/// Change ids are only partially ordered: one change id is ahead of another if none of its
+/// shards is behind the other's.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ChangeId {
-    pub(crate) inner: String,
+    pub(crate) shards: Vec<u64>,
+}
+
+impl ChangeId {
+    /// The counters of all shards
+    pub fn shards(&self) -> &[u64] {
+        &self.shards
+    }
+
+    /// How many changes `self` is behind `head`, summed up over all shards.
+    ///
+    /// Shards where `self` is ahead of `head` don't count. Returns `None` if both change
+    /// ids have a different number of shards.
+    pub fn lag(&self, head: &ChangeId) -> Option<u64> {
+        if self.shards.len() != head.shards.len() {
+            return None;
+        }
+
+        Some(
+            self.shards
+                .iter()
+                .zip(&head.shards)
+                .map(|(own, head)| head.saturating_sub(*own))
+                .sum(),
+        )
+    }
+}
+
+impl PartialOrd for ChangeId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.shards.len() != other.shards.len() {
+            return None;
+        }
+
+        self.shards
+            .iter()
+            .zip(&other.shards)
+            .map(|(a, b)| a.cmp(b))
+            .try_fold(Ordering::Equal, |acc, ord| match (acc, ord) {
+                (acc, Ordering::Equal) => Some(acc),
+                (Ordering::Equal, ord) => Some(ord),
+                (acc, ord) if acc == ord => Some(acc),
+                _ => None,
+            })
+    }
 }
 
 impl Display for ChangeId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&self.inner)
+        let shards = self
+            .shards
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+        f.write_str(&shards.join("-"))
     }
 }
 
@@ -15,21 +71,30 @@ impl std::str::FromStr for ChangeId {
     type Err = Box<dyn std::error::Error>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let is_valid = s.split('-').map(|x| x.parse::<u32>()).all(|x| x.is_ok());
-
-        if is_valid {
-            Ok(Self {
-                inner: s.to_owned(),
-            })
-        } else {
-            Err(format!("Failed parsing ChangeId {s}").into())
-        }
+        s.split('-')
+            .map(|x| x.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map(|shards| Self { shards })
+            .map_err(|_| format!("Failed parsing ChangeId {s}").into())
     }
 }
 
 impl From<ChangeId> for String {
     fn from(change_id: ChangeId) -> Self {
-        change_id.inner
+        change_id.to_string()
+    }
+}
+
+impl Serialize for ChangeId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChangeId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
     }
 }
 
@@ -44,7 +109,7 @@ mod test {
 
         assert!(change_id.is_ok(),);
         assert_eq!(
-            change_id.unwrap().inner,
+            change_id.unwrap().to_string(),
             "850662131-863318628-825558626-931433265-890834941"
         );
     }
@@ -55,4 +120,32 @@ mod test {
             super::ChangeId::from_str("850662A31-863318628-825558626-931433265-890834941").is_err(),
         );
     }
+
+    #[test]
+    fn test_ordering_and_lag() {
+        let a = ChangeId::from_str("10-20-30").unwrap();
+        let b = ChangeId::from_str("11-20-35").unwrap();
+        let c = ChangeId::from_str("12-19-40").unwrap();
+
+        assert!(a < b);
+        assert!(b > a);
+        assert!(a <= a);
+        assert_eq!(b.partial_cmp(&c), None);
+        assert_eq!(a.partial_cmp(&ChangeId::from_str("10-20").unwrap()), None);
+
+        assert_eq!(a.lag(&b), Some(6));
+        assert_eq!(b.lag(&a), Some(0));
+        assert_eq!(b.lag(&c), Some(6));
+        assert_eq!(a.lag(&ChangeId::from_str("10").unwrap()), None);
+    }
+
+    #[test]
+    fn test_serde_as_string() {
+        let change_id = ChangeId::from_str("1-2-3").unwrap();
+        let json = serde_json::to_string(&change_id).unwrap();
+
+        assert_eq!(json, "\"1-2-3\"");
+        assert_eq!(serde_json::from_str::<ChangeId>(&json).unwrap(), change_id);
+        assert!(serde_json::from_str::<ChangeId>("\"1-x\"").is_err());
+    }
 }
@@ -0,0 +1,107 @@
+use std::fmt::Display;
+
+use crate::{common::ChangeId, poe_api::auth::OAuthError};
+
+/// Everything that can go wrong while the indexer follows the river.
+///
+/// Errors are delivered as [`crate::r#async::indexer::IndexerMessage::Error`], so consumers
+/// can decide whether to keep going or shut down. The indexer itself keeps retrying unless
+/// the error [`is_fatal`](StashApiError::is_fatal).
+#[derive(Debug)]
+pub enum StashApiError {
+    /// Requesting or downloading a page failed
+    Network {
+        change_id: ChangeId,
+        source: reqwest_middleware::Error,
+    },
+    /// Fetching an OAuth access token failed or the API rejected it
+    Auth(OAuthError),
+    /// A page could not be deserialized
+    Decode {
+        change_id: ChangeId,
+        source: serde_json::Error,
+    },
+    /// The API responded with something unexpected, ie. an invalid next change id
+    Protocol { change_id: ChangeId, reason: String },
+    /// An archive of stashes could not be read while replaying it
+    Archive { location: String, reason: String },
+    /// A page kept failing to deserialize and was skipped as far as necessary to move on.
+    ///
+    /// `dead_letter` is where its raw body was written to, if anywhere.
+    Quarantined {
+        change_id: ChangeId,
+        skipped_stashes: usize,
+        reason: String,
+        dead_letter: Option<String>,
+    },
+}
+
+impl StashApiError {
+    /// Whether the indexer gave up and won't deliver any more data
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, StashApiError::Auth(e) if e.is_permanent())
+    }
+
+    /// The change id of the page that caused the error, if any
+    pub fn change_id(&self) -> Option<&ChangeId> {
+        match self {
+            StashApiError::Network { change_id, .. }
+            | StashApiError::Decode { change_id, .. }
+            | StashApiError::Protocol { change_id, .. }
+            | StashApiError::Quarantined { change_id, .. } => Some(change_id),
+            StashApiError::Auth(_) | StashApiError::Archive { .. } => None,
+        }
+    }
+}
+
+impl Display for StashApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StashApiError::Network { change_id, source } => {
+                write!(f, "Failed fetching change id {change_id}: {source}")
+            }
+            StashApiError::Auth(e) => write!(f, "{e}"),
+            StashApiError::Decode { change_id, source } => {
+                write!(f, "Failed deserializing change id {change_id}: {source}")
+            }
+            StashApiError::Protocol { change_id, reason } => {
+                write!(f, "Unexpected response for change id {change_id}: {reason}")
+            }
+            StashApiError::Archive { location, reason } => {
+                write!(f, "Failed reading archive {location}: {reason}")
+            }
+            StashApiError::Quarantined {
+                change_id,
+                skipped_stashes,
+                reason,
+                dead_letter,
+            } => {
+                write!(
+                    f,
+                    "Quarantined change id {change_id}, skipping {skipped_stashes} stashes: {reason}"
+                )?;
+                match dead_letter {
+                    Some(dead_letter) => write!(f, " (raw body at {dead_letter})"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for StashApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StashApiError::Network { source, .. } => Some(source),
+            StashApiError::Auth(e) => Some(e),
+            StashApiError::Decode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<OAuthError> for StashApiError {
+    fn from(e: OAuthError) -> Self {
+        StashApiError::Auth(e)
+    }
+}
@@ -0,0 +1,378 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::poe_api::poe_stash_api::protocol::Item;
+
+/// One shared interpretation of a [`protocol::Item`](Item), so consumers don't have to
+/// pattern-match the raw strings and numbers of the API themselves.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParsedItem {
+    pub id: Option<String>,
+    pub name: String,
+    pub type_line: String,
+    pub base_type: String,
+    pub rarity: Option<Rarity>,
+    pub frame_type: Option<FrameType>,
+    pub category: ItemCategory,
+    pub influences: Vec<Influence>,
+    pub mods: ItemMods,
+    pub item_level: u8,
+    pub identified: bool,
+    pub corrupted: bool,
+    pub stack_size: Option<u16>,
+    pub links: u8,
+    pub quality: Option<u8>,
+    pub gem_level: Option<u8>,
+    pub map_tier: Option<u8>,
+    pub note: Option<String>,
+}
+
+impl From<&Item> for ParsedItem {
+    fn from(item: &Item) -> Self {
+        let frame_type = item.frame_type.and_then(|f| FrameType::try_from(f).ok());
+
+        Self {
+            id: item.id.clone(),
+            name: item.name.clone(),
+            type_line: item.type_line.clone(),
+            base_type: item.base_type.clone(),
+            rarity: item
+                .rarity
+                .as_deref()
+                .and_then(|r| r.parse().ok())
+                .or_else(|| frame_type.and_then(FrameType::rarity)),
+            frame_type,
+            category: ItemCategory::of(item, frame_type),
+            influences: Influence::of(item),
+            mods: ItemMods::from(item),
+            item_level: item.item_level.unwrap_or(item.ilvl),
+            identified: item.identified,
+            corrupted: item.corrupted.unwrap_or(false),
+            stack_size: item.stack_size,
+            links: item.links(),
+            quality: item.quality(),
+            gem_level: item.gem_level(),
+            map_tier: item.map_tier(),
+            note: item.note.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Rarity {
+    Normal,
+    Magic,
+    Rare,
+    Unique,
+}
+
+impl FromStr for Rarity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Normal" => Ok(Rarity::Normal),
+            "Magic" => Ok(Rarity::Magic),
+            "Rare" => Ok(Rarity::Rare),
+            "Unique" => Ok(Rarity::Unique),
+            _ => Err(format!("unknown rarity {s}")),
+        }
+    }
+}
+
+/// See https://www.pathofexile.com/developer/docs/reference#type-FrameType
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FrameType {
+    Normal,
+    Magic,
+    Rare,
+    Unique,
+    Gem,
+    Currency,
+    DivinationCard,
+    Quest,
+    Prophecy,
+    Foil,
+    SupporterFoil,
+    Necropolis,
+    Gold,
+    BreachSplinter,
+}
+
+impl FrameType {
+    /// The rarity that items with this frame have, if any
+    pub fn rarity(self) -> Option<Rarity> {
+        match self {
+            FrameType::Normal => Some(Rarity::Normal),
+            FrameType::Magic => Some(Rarity::Magic),
+            FrameType::Rare => Some(Rarity::Rare),
+            FrameType::Unique | FrameType::Foil | FrameType::SupporterFoil => Some(Rarity::Unique),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for FrameType {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(FrameType::Normal),
+            1 => Ok(FrameType::Magic),
+            2 => Ok(FrameType::Rare),
+            3 => Ok(FrameType::Unique),
+            4 => Ok(FrameType::Gem),
+            5 => Ok(FrameType::Currency),
+            6 => Ok(FrameType::DivinationCard),
+            7 => Ok(FrameType::Quest),
+            8 => Ok(FrameType::Prophecy),
+            9 => Ok(FrameType::Foil),
+            10 => Ok(FrameType::SupporterFoil),
+            11 => Ok(FrameType::Necropolis),
+            12 => Ok(FrameType::Gold),
+            13 => Ok(FrameType::BreachSplinter),
+            _ => Err(format!("unknown frame type {value}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Influence {
+    Shaper,
+    Elder,
+    Crusader,
+    Redeemer,
+    Hunter,
+    Warlord,
+    Searing,
+    Tangled,
+}
+
+impl Influence {
+    const NAMES: [(&'static str, Influence); 6] = [
+        ("shaper", Influence::Shaper),
+        ("elder", Influence::Elder),
+        ("crusader", Influence::Crusader),
+        ("redeemer", Influence::Redeemer),
+        ("hunter", Influence::Hunter),
+        ("warlord", Influence::Warlord),
+    ];
+
+    /// Influences are spread over the `influences` object and the flags of shaped,
+    /// elder and eldritch items
+    fn of(item: &Item) -> Vec<Influence> {
+        let mut influences = Influence::NAMES
+            .into_iter()
+            .filter(|(name, _)| {
+                item.influences
+                    .as_ref()
+                    .and_then(|i| i.get(name))
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false)
+            })
+            .map(|(_, influence)| influence)
+            .collect::<Vec<_>>();
+
+        let flags = [
+            (item.shaper, Influence::Shaper),
+            (item.elder, Influence::Elder),
+            (item.searing, Influence::Searing),
+            (item.tangled, Influence::Tangled),
+        ];
+        for (flag, influence) in flags {
+            if flag == Some(true) && !influences.contains(&influence) {
+                influences.push(influence);
+            }
+        }
+
+        influences
+    }
+}
+
+/// A rough classification of items, since the river does not tell item classes apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ItemCategory {
+    Currency,
+    Gem,
+    DivinationCard,
+    Map,
+    Jewel,
+    Flask,
+    Accessory,
+    Equipment,
+    Other,
+}
+
+impl ItemCategory {
+    fn of(item: &Item, frame_type: Option<FrameType>) -> ItemCategory {
+        match frame_type {
+            Some(FrameType::Currency) => return ItemCategory::Currency,
+            Some(FrameType::Gem) => return ItemCategory::Gem,
+            Some(FrameType::DivinationCard) => return ItemCategory::DivinationCard,
+            _ => {}
+        }
+
+        let base_type = item.base_type.as_str();
+        if item.map_tier().is_some() {
+            ItemCategory::Map
+        } else if base_type.ends_with("Jewel") {
+            ItemCategory::Jewel
+        } else if base_type.contains("Flask") {
+            ItemCategory::Flask
+        } else if ["Ring", "Amulet", "Talisman", "Belt", "Sash"]
+            .iter()
+            .any(|suffix| base_type.ends_with(suffix))
+        {
+            ItemCategory::Accessory
+        } else if item.sockets.is_some() || item.requirements.is_some() {
+            ItemCategory::Equipment
+        } else {
+            ItemCategory::Other
+        }
+    }
+}
+
+/// All mods of an item by where they come from
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ItemMods {
+    pub implicit: Vec<Mod>,
+    pub explicit: Vec<Mod>,
+    pub crafted: Vec<Mod>,
+    pub fractured: Vec<Mod>,
+    pub enchant: Vec<Mod>,
+    pub utility: Vec<Mod>,
+    pub scourge: Vec<Mod>,
+    pub crucible: Vec<Mod>,
+    pub rune: Vec<Mod>,
+}
+
+impl ItemMods {
+    pub fn iter(&self) -> impl Iterator<Item = &Mod> {
+        self.implicit
+            .iter()
+            .chain(&self.explicit)
+            .chain(&self.crafted)
+            .chain(&self.fractured)
+            .chain(&self.enchant)
+            .chain(&self.utility)
+            .chain(&self.scourge)
+            .chain(&self.crucible)
+            .chain(&self.rune)
+    }
+}
+
+impl From<&Item> for ItemMods {
+    fn from(item: &Item) -> Self {
+        let parse = |mods: &Option<Vec<String>>| {
+            mods.iter()
+                .flatten()
+                .map(|text| Mod::parse(text))
+                .collect::<Vec<_>>()
+        };
+
+        Self {
+            implicit: parse(&item.implicit_mods),
+            explicit: parse(&item.explicit_mods),
+            crafted: parse(&item.crafted_mods),
+            fractured: parse(&item.fractured_mods),
+            enchant: parse(&item.enchant_mods),
+            utility: parse(&item.utility_mods),
+            scourge: parse(&item.scourge_mods),
+            crucible: parse(&item.crucible_mods),
+            rune: parse(&item.rune_mods),
+        }
+    }
+}
+
+/// A single mod, split into a template that is the same for all rolls and its values.
+///
+/// `+42 to maximum Life` turns into the template `+# to maximum Life` with the value `42`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mod {
+    pub text: String,
+    pub template: String,
+    pub values: Vec<f64>,
+}
+
+impl Mod {
+    pub fn parse(text: &str) -> Self {
+        let mut template = String::with_capacity(text.len());
+        let mut values = vec![];
+        let mut chars = text.char_indices().peekable();
+
+        while let Some((start, c)) = chars.next() {
+            if !c.is_ascii_digit() {
+                template.push(c);
+                continue;
+            }
+
+            let mut end = start + 1;
+            while let Some(&(i, c)) = chars.peek() {
+                let is_fraction = c == '.'
+                    && text[i + 1..]
+                        .chars()
+                        .next()
+                        .is_some_and(|c| c.is_ascii_digit());
+                if !c.is_ascii_digit() && !is_fraction {
+                    break;
+                }
+                end = i + 1;
+                chars.next();
+            }
+
+            match text[start..end].parse() {
+                Ok(value) => {
+                    values.push(value);
+                    template.push('#');
+                }
+                Err(_) => template.push_str(&text[start..end]),
+            }
+        }
+
+        Self {
+            text: text.to_string(),
+            template,
+            values,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FrameType, Influence, ItemCategory, Mod, ParsedItem, Rarity};
+    use crate::poe_api::poe_stash_api::protocol::Item;
+
+    #[test]
+    fn test_parse_mod() {
+        let m = Mod::parse("Adds 12 to 24.5 Physical Damage");
+        assert_eq!(m.template, "Adds # to # Physical Damage");
+        assert_eq!(m.values, vec![12.0, 24.5]);
+
+        let m = Mod::parse("+1 to Level of all Minion Skill Gems.");
+        assert_eq!(m.template, "+# to Level of all Minion Skill Gems.");
+        assert!(Mod::parse("Cannot be Frozen").values.is_empty());
+    }
+
+    #[test]
+    fn test_parse_item() {
+        let item = serde_json::from_str::<Item>(
+            r#"{"verified": false, "w": 1, "h": 1, "icon": "", "name": "Doom Loop",
+            "typeLine": "Two-Stone Ring", "baseType": "Two-Stone Ring", "identified": true,
+            "ilvl": 84, "frameType": 2, "influences": {"hunter": true}, "elder": true,
+            "implicitMods": ["+14% to Fire and Cold Resistances"],
+            "explicitMods": ["+70 to maximum Life", "Cannot be Frozen"]}"#,
+        )
+        .unwrap();
+
+        let parsed = ParsedItem::from(&item);
+
+        assert_eq!(parsed.rarity, Some(Rarity::Rare));
+        assert_eq!(parsed.frame_type, Some(FrameType::Rare));
+        assert_eq!(parsed.category, ItemCategory::Accessory);
+        assert_eq!(parsed.influences, vec![Influence::Hunter, Influence::Elder]);
+        assert_eq!(parsed.item_level, 84);
+        assert_eq!(parsed.mods.iter().count(), 3);
+        assert_eq!(parsed.mods.explicit[0].template, "+# to maximum Life");
+    }
+}
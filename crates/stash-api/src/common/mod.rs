@@ -1,6 +1,13 @@
 mod change_id;
-pub mod parse;
+mod error;
+pub mod item;
+pub mod page_parser;
+mod parse;
 pub mod poe_ninja_client;
+pub mod realm;
+pub mod recording;
+pub mod schema;
 pub mod stash;
 
 pub use change_id::ChangeId;
+pub use error::StashApiError;
@@ -0,0 +1,400 @@
+use serde::de::Error;
+
+use super::parse::ValueScanner;
+use crate::poe_api::poe_stash_api::protocol::PublicStashChange;
+
+/// Incrementally parses a page of the Public Stash Tab API while its body streams in.
+///
+/// Every [`PublicStashChange`] is deserialized as soon as it is complete, so only the
+/// stash that is currently being downloaded has to be buffered. `next_change_id` is
+/// available as soon as it was read, which usually is right at the start of a page.
+///
+/// A [`lenient`](PageParser::lenient) parser skips stashes that fail to deserialize instead
+/// of failing the whole page.
+#[derive(Debug, Default)]
+pub struct PageParser {
+    /// Bytes that are not consumed yet
+    buffer: Vec<u8>,
+    /// Number of bytes that were consumed and dropped from `buffer`
+    consumed: usize,
+    state: State,
+    scanner: ValueScanner,
+    next_change_id: Option<String>,
+    has_stashes: Option<bool>,
+    lenient: bool,
+    skipped: Vec<serde_json::Error>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+enum State {
+    /// Expecting the opening brace of the page
+    #[default]
+    Start,
+    /// Expecting a key or the closing brace of the page
+    Key,
+    /// Expecting the colon after a key
+    Colon(String),
+    /// Expecting the value of a key
+    Value(String),
+    /// Expecting a comma or the closing brace after a value
+    AfterValue,
+    /// Expecting a stash or the end of the stashes array
+    Stash,
+    /// Expecting a comma or the end of the stashes array after a stash
+    AfterStash,
+    /// The page is complete
+    Done,
+}
+
+impl PageParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A parser that skips stashes which fail to deserialize, see [`PageParser::skipped`]
+    pub fn lenient() -> Self {
+        Self {
+            lenient: true,
+            ..Self::default()
+        }
+    }
+
+    /// Feeds the next chunk of the body and returns all stashes that are complete by now.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<PublicStashChange>, serde_json::Error> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut stashes = vec![];
+        let mut pos = 0;
+
+        loop {
+            if !self.scanner.is_started() {
+                while self.buffer.get(pos).is_some_and(u8::is_ascii_whitespace) {
+                    pos += 1;
+                }
+            }
+            let Some(&byte) = self.buffer.get(pos) else {
+                break;
+            };
+
+            match (&self.state, byte) {
+                (State::Start, b'{') => {
+                    pos += 1;
+                    self.state = State::Key;
+                }
+                (State::Key, b'}') | (State::AfterValue, b'}') => {
+                    pos += 1;
+                    self.state = State::Done;
+                }
+                (State::Key, b'"') => match self.scanner.scan(&self.buffer[pos..]) {
+                    Some(len) => {
+                        let key = serde_json::from_slice::<String>(&self.buffer[pos..pos + len])?;
+                        pos += len;
+                        self.state = State::Colon(key);
+                    }
+                    None => break,
+                },
+                (State::Colon(key), b':') => {
+                    pos += 1;
+                    self.state = State::Value(key.clone());
+                }
+                (State::Value(key), b'[') if key == "stashes" => {
+                    pos += 1;
+                    self.state = State::Stash;
+                }
+                (State::Value(key), _) => match self.scanner.scan(&self.buffer[pos..]) {
+                    Some(len) => {
+                        if key == "next_change_id" {
+                            self.next_change_id =
+                                Some(serde_json::from_slice(&self.buffer[pos..pos + len])?);
+                        }
+                        pos += len;
+                        self.state = State::AfterValue;
+                    }
+                    None => break,
+                },
+                (State::AfterValue, b',') => {
+                    pos += 1;
+                    self.state = State::Key;
+                }
+                (State::Stash, b']') | (State::AfterStash, b']') => {
+                    pos += 1;
+                    self.has_stashes.get_or_insert(false);
+                    self.state = State::AfterValue;
+                }
+                (State::Stash, _) => {
+                    self.has_stashes = Some(true);
+                    match self.scanner.scan(&self.buffer[pos..]) {
+                        Some(len) => {
+                            match serde_json::from_slice(&self.buffer[pos..pos + len]) {
+                                Ok(stash) => stashes.push(stash),
+                                Err(e) if self.lenient => self.skipped.push(e),
+                                Err(e) => return Err(e),
+                            }
+                            pos += len;
+                            self.state = State::AfterStash;
+                        }
+                        None => break,
+                    }
+                }
+                (State::AfterStash, b',') => {
+                    pos += 1;
+                    self.state = State::Stash;
+                }
+                (state, byte) => {
+                    return Err(serde_json::Error::custom(format!(
+                        "unexpected {:?} at byte {} while parsing {:?}",
+                        byte as char,
+                        self.consumed + pos,
+                        state
+                    )))
+                }
+            }
+        }
+
+        self.buffer.drain(..pos);
+        self.consumed += pos;
+
+        Ok(stashes)
+    }
+
+    /// The `next_change_id` of the page, once it was read
+    pub fn next_change_id(&self) -> Option<&str> {
+        self.next_change_id.as_deref()
+    }
+
+    /// Whether the page holds any stashes, once the start of the `stashes` array was read
+    pub fn has_stashes(&self) -> Option<bool> {
+        self.has_stashes
+    }
+
+    /// Why each stash that a lenient parser skipped failed to deserialize
+    pub fn skipped(&self) -> &[serde_json::Error] {
+        &self.skipped
+    }
+
+    /// Makes sure that the page is complete and returns its `next_change_id`
+    pub fn finish(self) -> Result<String, serde_json::Error> {
+        if self.state != State::Done {
+            return Err(serde_json::Error::custom(format!(
+                "unexpected end of page after {} bytes",
+                self.consumed + self.buffer.len()
+            )));
+        }
+
+        self.next_change_id
+            .ok_or_else(|| serde_json::Error::missing_field("next_change_id"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::PageParser;
+
+    const PAGE: &str = r#"{
+        "stashes": [
+            {"id": "a", "public": true, "accountName": "foo", "stash": "~b/o 1 \"chaos\" }]",
+             "stashType": "PremiumStash", "league": "Standard", "items": []},
+            {"id": "b", "public": false, "accountName": null, "stash": null,
+             "stashType": "PremiumStash", "league": null, "items": []}
+        ],
+        "next_change_id" : "1-2-3",
+        "unknown": [1, {"nested": -2.5e3}, true]
+    }"#;
+
+    #[test]
+    fn test_parse_page_byte_by_byte() {
+        let mut parser = PageParser::new();
+        let mut stashes = vec![];
+        for byte in PAGE.as_bytes() {
+            stashes.extend(parser.feed(&[*byte]).unwrap());
+        }
+
+        assert_eq!(parser.has_stashes(), Some(true));
+        assert_eq!(parser.next_change_id(), Some("1-2-3"));
+        assert_eq!(parser.finish().unwrap(), "1-2-3");
+        assert_eq!(stashes.len(), 2);
+        assert_eq!(stashes[0].stash.as_deref(), Some("~b/o 1 \"chaos\" }]"));
+        assert_eq!(stashes[1].id, "b");
+    }
+
+    #[test]
+    fn test_parse_empty_page() {
+        let mut parser = PageParser::new();
+        let stashes = parser
+            .feed(br#"{"next_change_id": "1-1", "stashes": []}"#)
+            .unwrap();
+
+        assert!(stashes.is_empty());
+        assert_eq!(parser.has_stashes(), Some(false));
+        assert_eq!(parser.finish().unwrap(), "1-1");
+    }
+
+    #[test]
+    fn test_parse_page_leniently() {
+        let page = br#"{"next_change_id": "1-1", "stashes": [{"id": 1}, {"id": "a",
+            "public": true, "accountName": null, "stash": null, "stashType": "PremiumStash",
+            "league": null, "items": []}]}"#;
+
+        let mut parser = PageParser::lenient();
+        let stashes = parser.feed(page).unwrap();
+
+        assert_eq!(stashes.len(), 1);
+        assert_eq!(stashes[0].id, "a");
+        assert_eq!(parser.skipped().len(), 1);
+        assert_eq!(parser.finish().unwrap(), "1-1");
+    }
+
+    #[test]
+    fn test_parse_malformed_page() {
+        assert!(PageParser::new().feed(b"[]").is_err());
+        assert!(PageParser::new()
+            .feed(br#"{"next_change_id": "1-1", "stashes": [{"id": 1}]}"#)
+            .is_err());
+
+        let mut truncated = PageParser::new();
+        truncated
+            .feed(br#"{"next_change_id": "1-1", "stashes": ["#)
+            .unwrap();
+        assert!(truncated.finish().is_err());
+    }
+
+    /// A page with its keys in the given order, separated by arbitrary whitespace
+    fn page(
+        change_id: &str,
+        stashes: &[String],
+        extra: &[String],
+        order: &[usize],
+        ws: &[String],
+    ) -> String {
+        let mut entries = vec![
+            format!("\"next_change_id\"{}:{}\"{change_id}\"", ws[0], ws[1]),
+            format!(
+                "\"stashes\"{}:{}[{}{}]",
+                ws[2],
+                ws[3],
+                stashes.join(&format!("{},{}", ws[4], ws[5])),
+                ws[6]
+            ),
+        ];
+        entries.extend(
+            extra
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("\"extra_{i}\":{}{value}", ws[7])),
+        );
+
+        let entries = order
+            .iter()
+            .map(|i| entries[i % entries.len()].clone())
+            .collect::<Vec<_>>();
+        format!(
+            "{{{}{}{}}}",
+            ws[8],
+            entries.join(&format!("{},", ws[9])),
+            ws[10]
+        )
+    }
+
+    fn stash(id: usize) -> String {
+        format!(
+            r#"{{"id": "{id}", "public": true, "accountName": null,
+            "stash": "\"next_change_id\": \"0-0\" }}]", "stashType": "PremiumStash",
+            "league": null, "items": []}}"#
+        )
+    }
+
+    fn json_value() -> impl Strategy<Value = String> {
+        prop_oneof![
+            Just("null".to_string()),
+            Just("-12.5e3".to_string()),
+            Just("true".to_string()),
+            Just(r#""next_change_id""#.to_string()),
+            Just(r#"[1, {"a": "}]\"", "next_change_id": "1-1"}]"#.to_string()),
+        ]
+    }
+
+    /// Splits `bytes` into chunks of the given sizes, the last chunk holds the rest
+    fn chunks<'a>(bytes: &'a [u8], sizes: &[usize]) -> Vec<&'a [u8]> {
+        let mut chunks = vec![];
+        let mut rest = bytes;
+        for size in sizes {
+            let (chunk, tail) = rest.split_at((*size).min(rest.len()));
+            chunks.push(chunk);
+            rest = tail;
+        }
+        chunks.push(rest);
+        chunks
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_any_key_order(
+            shards in prop::collection::vec(0u32..u32::MAX, 1..6),
+            stash_count in 0usize..3,
+            extra in prop::collection::vec(json_value(), 0..3),
+            order in Just((0..5).collect::<Vec<usize>>()).prop_shuffle(),
+            ws in prop::collection::vec("[ \t\r\n]{0,2}", 11),
+            sizes in prop::collection::vec(0usize..32, 0..64),
+        ) {
+            let change_id = shards
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join("-");
+            let stashes = (0..stash_count).map(stash).collect::<Vec<_>>();
+            // Every key exactly once
+            let order = order.into_iter().filter(|i| *i < extra.len() + 2).collect::<Vec<_>>();
+            let page = page(&change_id, &stashes, &extra, &order, &ws);
+
+            let mut parser = PageParser::new();
+            let mut ids = vec![];
+            for chunk in chunks(page.as_bytes(), &sizes) {
+                ids.extend(parser.feed(chunk).unwrap().into_iter().map(|s| s.id));
+
+                // Values are either already known or need more data
+                if let Some(next_change_id) = parser.next_change_id() {
+                    prop_assert_eq!(next_change_id, change_id.as_str());
+                }
+                if let Some(has_stashes) = parser.has_stashes() {
+                    prop_assert_eq!(has_stashes, stash_count > 0);
+                }
+            }
+
+            prop_assert_eq!(ids, (0..stash_count).map(|i| i.to_string()).collect::<Vec<_>>());
+            prop_assert_eq!(parser.has_stashes(), Some(stash_count > 0));
+            prop_assert_eq!(parser.finish().unwrap(), change_id);
+        }
+
+        #[test]
+        fn test_parse_arbitrary_bytes(
+            prefix in 0..PAGE.len(),
+            tail in prop::collection::vec(any::<u8>(), 0..256),
+            sizes in prop::collection::vec(0usize..32, 0..16),
+        ) {
+            // Garbage after a valid start of a page, to get past the opening brace
+            let bytes = [&PAGE.as_bytes()[..prefix], &tail].concat();
+            let whole = PageParser::new().feed(&bytes).map(|stashes| stashes.len());
+
+            // Splitting the body into chunks does not change the outcome
+            let mut parser = PageParser::new();
+            let mut chunked = Ok(0);
+            for chunk in chunks(&bytes, &sizes) {
+                match parser.feed(chunk) {
+                    Ok(stashes) => chunked = chunked.map(|n| n + stashes.len()),
+                    Err(e) => {
+                        chunked = Err(e);
+                        break;
+                    }
+                }
+            }
+
+            prop_assert_eq!(whole.is_ok(), chunked.is_ok());
+            if let (Ok(whole), Ok(chunked)) = (whole, chunked) {
+                prop_assert_eq!(whole, chunked);
+                let _ = parser.finish();
+            }
+        }
+    }
+}
@@ -1,27 +1,62 @@
-use std::string::FromUtf8Error;
+/// Finds the end of a single JSON value, while it might still be incomplete.
+///
+/// Keeps track of how far it got, so scanning the same value again after more data
+/// arrived continues where it left off.
+#[derive(Debug, Default)]
+pub(crate) struct ValueScanner {
+    /// How many bytes of the current value were scanned
+    offset: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
 
-use super::ChangeId;
-use std::str::FromStr;
+impl ValueScanner {
+    pub(crate) fn is_started(&self) -> bool {
+        self.offset > 0
+    }
 
-pub fn parse_change_id_from_bytes(bytes: &[u8]) -> Result<ChangeId, FromUtf8Error> {
-    String::from_utf8(bytes.split(|b| b.eq(&b'"')).nth(3).unwrap().to_vec())
-        .map(|s| ChangeId::from_str(&s).expect("Valid ChangeId"))
-}
+    /// Returns the length of the value at the start of `bytes` once it is complete
+    pub(crate) fn scan(&mut self, bytes: &[u8]) -> Option<usize> {
+        while let Some(&byte) = bytes.get(self.offset) {
+            let scalar = self.offset > 0 && self.depth == 0 && !self.in_string;
+            self.offset += 1;
 
-#[cfg(test)]
-mod test {
+            if self.in_string {
+                match byte {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => {
+                        self.in_string = false;
+                        if self.depth == 0 {
+                            return Some(self.reset());
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
 
-    use crate::common::{parse::parse_change_id_from_bytes, ChangeId};
+            match byte {
+                // Numbers and literals end right before the next delimiter
+                b',' | b'}' | b']' if scalar => return Some(self.reset() - 1),
+                _ if scalar && byte.is_ascii_whitespace() => return Some(self.reset() - 1),
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 0 {
+                        return Some(self.reset());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
 
-    #[test]
-    fn test_parse_change_id_from_bytes() {
-        let input = b"{\"next_change_id\": \"1882903321-1878868410-1818903289-2014357625-1957236232\", \"stashes\": []}";
-        let result = parse_change_id_from_bytes(input);
-        assert_eq!(
-            result,
-            Ok(ChangeId {
-                inner: "1882903321-1878868410-1818903289-2014357625-1957236232".into()
-            })
-        );
+    fn reset(&mut self) -> usize {
+        std::mem::take(self).offset
     }
 }
@@ -1,21 +1,46 @@
 use crate::common::ChangeId;
 use serde::Deserialize;
 use std::str::FromStr;
+use trade_common::{telemetry::generate_http_client, ClientWithMiddleware};
+
+pub const DEFAULT_POE_NINJA_BASE_URL: &str = "https://poe.ninja";
 
 #[derive(Debug, Deserialize)]
 struct PoeNinjaGetStats {
     next_change_id: String,
 }
 
-#[derive(Debug)]
-pub struct PoeNinjaClient;
+#[derive(Debug, Clone)]
+pub struct PoeNinjaClient {
+    base_url: String,
+    client: ClientWithMiddleware,
+}
+
+impl Default for PoeNinjaClient {
+    fn default() -> Self {
+        Self::new(DEFAULT_POE_NINJA_BASE_URL, generate_http_client(None))
+    }
+}
 
 impl PoeNinjaClient {
-    pub async fn fetch_latest_change_id_async() -> Result<ChangeId, Box<dyn std::error::Error>> {
-        let response = reqwest::get("https://poe.ninja/api/Data/GetStats").await?;
+    pub fn new(base_url: impl Into<String>, client: ClientWithMiddleware) -> Self {
+        Self {
+            base_url: base_url.into(),
+            client,
+        }
+    }
+
+    pub async fn fetch_latest_change_id(&self) -> Result<ChangeId, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/Data/GetStats", self.base_url.trim_end_matches('/'));
+        let response = self.client.get(url).send().await?;
         let str = response.json::<PoeNinjaGetStats>().await?;
         ChangeId::from_str(&str.next_change_id)
     }
+
+    /// Fetches the latest change id from the public poe.ninja API.
+    pub async fn fetch_latest_change_id_async() -> Result<ChangeId, Box<dyn std::error::Error>> {
+        Self::default().fetch_latest_change_id().await
+    }
 }
 
 #[cfg(test)]
@@ -24,6 +49,6 @@ mod test {
     async fn test_fetch_latest_change_id_async() {
         let latest_change_id = super::PoeNinjaClient::fetch_latest_change_id_async().await;
         assert!(latest_change_id.is_ok());
-        assert!(latest_change_id.unwrap().inner.len() > 50);
+        assert!(latest_change_id.unwrap().to_string().len() > 50);
     }
 }
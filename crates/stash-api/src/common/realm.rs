@@ -0,0 +1,72 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// The realms that have their own river of public stash tabs.
+///
+/// See https://www.pathofexile.com/developer/docs/reference#publicstashes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Realm {
+    #[default]
+    Pc,
+    Xbox,
+    Sony,
+    Poe2,
+}
+
+impl Realm {
+    pub const ALL: [Realm; 4] = [Realm::Pc, Realm::Xbox, Realm::Sony, Realm::Poe2];
+
+    /// The path of the river of this realm, relative to the API's base URL
+    pub fn river_path(&self) -> String {
+        match self {
+            Realm::Pc => "/public-stash-tabs".into(),
+            realm => format!("/public-stash-tabs/{realm}"),
+        }
+    }
+
+    /// The OAuth scope that grants access to the river of this realm
+    pub fn oauth_scope(&self) -> &'static str {
+        // GGG currently uses the same scope for all realms
+        match self {
+            Realm::Pc | Realm::Xbox | Realm::Sony | Realm::Poe2 => "service:psapi",
+        }
+    }
+}
+
+impl Display for Realm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Realm::Pc => write!(f, "pc"),
+            Realm::Xbox => write!(f, "xbox"),
+            Realm::Sony => write!(f, "sony"),
+            Realm::Poe2 => write!(f, "poe2"),
+        }
+    }
+}
+
+impl FromStr for Realm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Realm::ALL
+            .into_iter()
+            .find(|realm| realm.to_string() == s.to_lowercase())
+            .ok_or_else(|| format!("unknown realm {s}, expected pc, xbox, sony or poe2"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Realm;
+
+    #[test]
+    fn test_realm() {
+        assert_eq!("POE2".parse(), Ok(Realm::Poe2));
+        assert!("ps4".parse::<Realm>().is_err());
+        assert_eq!(Realm::Pc.river_path(), "/public-stash-tabs");
+        assert_eq!(Realm::Sony.river_path(), "/public-stash-tabs/sony");
+        assert_eq!(serde_json::to_string(&Realm::Xbox).unwrap(), r#""xbox""#);
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    io::{Read, Write},
+    path::PathBuf,
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use super::ChangeId;
+
+/// Raw response bodies of the Public Stash Tab API, stored as `{change_id}.json.gz`.
+///
+/// Each change id is only recorded once, so the body that was seen first is kept, ie. the
+/// one that failed to deserialize rather than a later successful retry.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    dir: PathBuf,
+}
+
+impl Recording {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Where the body of `change_id` is stored
+    pub fn path(&self, change_id: &ChangeId) -> PathBuf {
+        self.dir.join(format!("{change_id}.json.gz"))
+    }
+
+    /// Stores `body` for `change_id`, unless it was recorded before
+    pub async fn write(&self, change_id: &ChangeId, body: Vec<u8>) -> std::io::Result<()> {
+        let dir = self.dir.clone();
+        let path = self.path(change_id);
+        tokio::task::spawn_blocking(move || {
+            if path.exists() {
+                return Ok(());
+            }
+            std::fs::create_dir_all(&dir)?;
+
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            encoder.write_all(&body)?;
+            // Write to a temporary file first so that readers never see a partial recording
+            let tmp = path.with_extension("gz.tmp");
+            std::fs::write(&tmp, encoder.finish()?)?;
+            std::fs::rename(tmp, path)
+        })
+        .await?
+    }
+
+    /// Reads the decompressed body of `change_id`
+    pub async fn read(&self, change_id: &ChangeId) -> std::io::Result<Vec<u8>> {
+        let compressed = tokio::fs::read(self.path(change_id)).await?;
+        tokio::task::spawn_blocking(move || {
+            let mut body = vec![];
+            GzDecoder::new(compressed.as_slice()).read_to_end(&mut body)?;
+            Ok(body)
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::{ChangeId, Recording};
+
+    #[tokio::test]
+    async fn test_recording_keeps_first_body() {
+        let dir = tempfile::tempdir().unwrap();
+        let recording = Recording::new(dir.path().join("nested"));
+        let change_id = ChangeId::from_str("1-2-3").unwrap();
+
+        recording
+            .write(&change_id, b"first".to_vec())
+            .await
+            .unwrap();
+        recording
+            .write(&change_id, b"second".to_vec())
+            .await
+            .unwrap();
+
+        assert!(recording.path(&change_id).ends_with("1-2-3.json.gz"));
+        assert_eq!(recording.read(&change_id).await.unwrap(), b"first");
+        assert!(recording
+            .read(&ChangeId::from_str("4-5-6").unwrap())
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,886 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use chrono::NaiveDateTime;
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use super::{realm::Realm, stash::Stash};
+use crate::poe_api::poe_stash_api::protocol::{
+    CrucibleItem, CrucibleNode, GemPage, GemTab, HybridItem, IncubatedItem, Item, ItemExtendedProp,
+    ItemProperty, ItemReward, ItemSocket, LogbookMods, RejectedItem, ScourgedItem, UltimatumMod,
+};
+
+/// How [`Stash`]es and their items are serialized for downstream consumers.
+///
+/// Both schemas are accepted when deserializing, so archives of either can be replayed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputSchema {
+    /// Our own field names, ie. `stack_size`
+    #[default]
+    SnakeCase,
+    /// The field names of GGG's API, ie. `stackSize`
+    CamelCase,
+}
+
+impl OutputSchema {
+    pub fn to_value<T: SerializeCamelCase + ?Sized>(&self, value: &T) -> serde_json::Result<Value> {
+        match self {
+            OutputSchema::SnakeCase => serde_json::to_value(value),
+            OutputSchema::CamelCase => serde_json::to_value(CamelCase(value)),
+        }
+    }
+
+    pub fn to_vec<T: SerializeCamelCase + ?Sized>(&self, value: &T) -> serde_json::Result<Vec<u8>> {
+        match self {
+            OutputSchema::SnakeCase => serde_json::to_vec(value),
+            OutputSchema::CamelCase => serde_json::to_vec(&CamelCase(value)),
+        }
+    }
+}
+
+impl FromStr for OutputSchema {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "snake_case" => Ok(OutputSchema::SnakeCase),
+            "camelCase" => Ok(OutputSchema::CamelCase),
+            _ => Err(format!(
+                "unknown schema {s}, expected snake_case or camelCase"
+            )),
+        }
+    }
+}
+
+impl Display for OutputSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputSchema::SnakeCase => write!(f, "snake_case"),
+            OutputSchema::CamelCase => write!(f, "camelCase"),
+        }
+    }
+}
+
+/// Serialization with the field names of GGG's API, see [`OutputSchema::CamelCase`].
+///
+/// Implemented with mirror structs that name every field explicitly, so that data such as
+/// the passthrough `extra` fields or reward names is never renamed.
+pub trait SerializeCamelCase: Serialize {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+}
+
+/// Serializes `T` with [`SerializeCamelCase`], ie. to nest it in a camelCase mirror struct
+pub struct CamelCase<'a, T: ?Sized>(pub &'a T);
+
+impl<T: SerializeCamelCase + ?Sized> Serialize for CamelCase<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize_camel_case(serializer)
+    }
+}
+
+impl<T: SerializeCamelCase + ?Sized> SerializeCamelCase for &T {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize_camel_case(serializer)
+    }
+}
+
+impl<T: SerializeCamelCase> SerializeCamelCase for [T] {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(CamelCase))
+    }
+}
+
+impl<T: SerializeCamelCase> SerializeCamelCase for Vec<T> {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_slice().serialize_camel_case(serializer)
+    }
+}
+
+impl<T: SerializeCamelCase> SerializeCamelCase for Option<T> {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Some(value) => serializer.serialize_some(&CamelCase(value)),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// Keys are data, so only the values are converted
+impl<T: SerializeCamelCase> SerializeCamelCase for HashMap<String, T> {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter().map(|(key, value)| (key, CamelCase(value))))
+    }
+}
+
+/// Types without snake_case field names serialize the same in both schemas
+macro_rules! same_in_camel_case {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl SerializeCamelCase for $t {
+                fn serialize_camel_case<S: Serializer>(
+                    &self,
+                    serializer: S,
+                ) -> Result<S::Ok, S::Error> {
+                    self.serialize(serializer)
+                }
+            }
+        )*
+    };
+}
+
+same_in_camel_case!(
+    RejectedItem,
+    ItemExtendedProp,
+    ItemReward,
+    LogbookMods,
+    UltimatumMod,
+    IncubatedItem,
+    ScourgedItem,
+);
+
+// The mirror structs below destructure their original without `..`, so that a new field
+// does not compile until it is added to its mirror as well.
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CamelCaseStash<'a> {
+    id: &'a str,
+    public: bool,
+    account_name: &'a Option<String>,
+    stash: &'a Option<String>,
+    stash_type: &'a str,
+    items: CamelCase<'a, [Item]>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    rejected_items: &'a [RejectedItem],
+    league: &'a Option<String>,
+    realm: &'a Realm,
+    created_at: &'a NaiveDateTime,
+    change_id: &'a str,
+    next_change_id: &'a str,
+    #[serde(flatten)]
+    extra: &'a Map<String, Value>,
+}
+
+impl SerializeCamelCase for Stash {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Stash {
+            id,
+            public,
+            account_name,
+            stash,
+            stash_type,
+            items,
+            rejected_items,
+            league,
+            realm,
+            created_at,
+            change_id,
+            next_change_id,
+            extra,
+        } = self;
+
+        CamelCaseStash {
+            id,
+            public: *public,
+            account_name,
+            stash,
+            stash_type,
+            items: CamelCase(items),
+            rejected_items,
+            league,
+            realm,
+            created_at,
+            change_id,
+            next_change_id,
+            extra,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CamelCaseItem<'a> {
+    realm: &'a Option<String>,
+    verified: bool,
+    w: u8,
+    h: u8,
+    icon: &'a str,
+    support: &'a Option<bool>,
+    stack_size: &'a Option<u16>,
+    max_stack_size: &'a Option<u16>,
+    stack_size_text: &'a Option<String>,
+    league: &'a Option<String>,
+    id: &'a Option<String>,
+    unidentified_tier: &'a Option<u8>,
+    influences: &'a Option<Value>,
+    elder: &'a Option<bool>,
+    shaper: &'a Option<bool>,
+    searing: &'a Option<bool>,
+    tangled: &'a Option<bool>,
+    memory_item: &'a Option<bool>,
+    abyss_jewel: &'a Option<bool>,
+    delve: &'a Option<bool>,
+    fractured: &'a Option<bool>,
+    synthesised: &'a Option<bool>,
+    sockets: CamelCase<'a, Option<Vec<ItemSocket>>>,
+    socketed_items: CamelCase<'a, Option<Vec<Item>>>,
+    name: &'a str,
+    type_line: &'a str,
+    base_type: &'a str,
+    rarity: &'a Option<String>,
+    identified: bool,
+    item_level: &'a Option<u8>,
+    ilvl: u8,
+    note: &'a Option<String>,
+    forum_note: &'a Option<String>,
+    locked_to_character: &'a Option<bool>,
+    locked_to_account: &'a Option<bool>,
+    duplicated: &'a Option<bool>,
+    split: &'a Option<bool>,
+    corrupted: &'a Option<bool>,
+    unmodifiable: &'a Option<bool>,
+    cis_race_reward: &'a Option<bool>,
+    sea_race_reward: &'a Option<bool>,
+    th_race_reward: &'a Option<bool>,
+    properties: CamelCase<'a, Option<Vec<ItemProperty>>>,
+    notable_properties: CamelCase<'a, Option<Vec<ItemProperty>>>,
+    requirements: CamelCase<'a, Option<Vec<ItemProperty>>>,
+    #[serde(rename = "weaponRequirements")]
+    weapon_properties: CamelCase<'a, Option<Vec<ItemProperty>>>,
+    support_gem_requirements: CamelCase<'a, Option<Vec<ItemProperty>>>,
+    additional_requirements: CamelCase<'a, Option<Vec<ItemProperty>>>,
+    next_level_requirements: CamelCase<'a, Option<Vec<ItemProperty>>>,
+    granted_skills: CamelCase<'a, Option<Vec<ItemProperty>>>,
+    talisman_tier: &'a Option<u8>,
+    rewards: &'a Option<Vec<ItemReward>>,
+    sec_descr_text: &'a Option<String>,
+    utility_mods: &'a Option<Vec<String>>,
+    logbook_mods: &'a Option<Vec<LogbookMods>>,
+    enchant_mods: &'a Option<Vec<String>>,
+    rune_mods: &'a Option<Vec<String>>,
+    scourge_mods: &'a Option<Vec<String>>,
+    implicit_mods: &'a Option<Vec<String>>,
+    ultimatum_mods: &'a Option<Vec<UltimatumMod>>,
+    explicit_mods: &'a Option<Vec<String>>,
+    crafted_mods: &'a Option<Vec<String>>,
+    fractured_mods: &'a Option<Vec<String>>,
+    crucible_mods: &'a Option<Vec<String>>,
+    cosmetic_mods: &'a Option<Vec<String>>,
+    veiled_mods: &'a Option<Vec<String>>,
+    veiled: &'a Option<bool>,
+    descr_text: &'a Option<String>,
+    flavour_text: &'a Option<Vec<String>>,
+    flavour_text_parsed: &'a Option<Vec<String>>,
+    flavour_text_note: &'a Option<String>,
+    prophecy_text: &'a Option<String>,
+    is_relic: &'a Option<bool>,
+    foil_variation: &'a Option<u8>,
+    replica: &'a Option<bool>,
+    foreseeing: &'a Option<bool>,
+    incubated_item: &'a Option<IncubatedItem>,
+    scourged: &'a Option<ScourgedItem>,
+    crucible: CamelCase<'a, Option<CrucibleItem>>,
+    ruthless: &'a Option<bool>,
+    frame_type: &'a Option<u8>,
+    art_filename: &'a Option<String>,
+    hybrid: CamelCase<'a, Option<HybridItem>>,
+    extended: &'a Option<ItemExtendedProp>,
+    x: &'a Option<u8>,
+    y: &'a Option<u8>,
+    inventory_id: &'a Option<String>,
+    socket: &'a Option<u8>,
+    colour: &'a Option<String>,
+    gem_sockets: &'a Option<Vec<String>>,
+    gem_tabs: CamelCase<'a, Option<Vec<GemTab>>>,
+    gem_background: &'a Option<String>,
+    gem_skill: &'a Option<String>,
+    #[serde(flatten)]
+    extra: &'a Map<String, Value>,
+}
+
+impl SerializeCamelCase for Item {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Item {
+            realm,
+            verified,
+            w,
+            h,
+            icon,
+            support,
+            stack_size,
+            max_stack_size,
+            stack_size_text,
+            league,
+            id,
+            unidentified_tier,
+            influences,
+            elder,
+            shaper,
+            searing,
+            tangled,
+            memory_item,
+            abyss_jewel,
+            delve,
+            fractured,
+            synthesised,
+            sockets,
+            socketed_items,
+            name,
+            type_line,
+            base_type,
+            rarity,
+            identified,
+            item_level,
+            ilvl,
+            note,
+            forum_note,
+            locked_to_character,
+            locked_to_account,
+            duplicated,
+            split,
+            corrupted,
+            unmodifiable,
+            cis_race_reward,
+            sea_race_reward,
+            th_race_reward,
+            properties,
+            notable_properties,
+            requirements,
+            weapon_properties,
+            support_gem_requirements,
+            additional_requirements,
+            next_level_requirements,
+            granted_skills,
+            talisman_tier,
+            rewards,
+            sec_descr_text,
+            utility_mods,
+            logbook_mods,
+            enchant_mods,
+            rune_mods,
+            scourge_mods,
+            implicit_mods,
+            ultimatum_mods,
+            explicit_mods,
+            crafted_mods,
+            fractured_mods,
+            crucible_mods,
+            cosmetic_mods,
+            veiled_mods,
+            veiled,
+            descr_text,
+            flavour_text,
+            flavour_text_parsed,
+            flavour_text_note,
+            prophecy_text,
+            is_relic,
+            foil_variation,
+            replica,
+            foreseeing,
+            incubated_item,
+            scourged,
+            crucible,
+            ruthless,
+            frame_type,
+            art_filename,
+            hybrid,
+            extended,
+            x,
+            y,
+            inventory_id,
+            socket,
+            colour,
+            gem_sockets,
+            gem_tabs,
+            gem_background,
+            gem_skill,
+            extra,
+        } = self;
+
+        CamelCaseItem {
+            realm,
+            verified: *verified,
+            w: *w,
+            h: *h,
+            icon,
+            support,
+            stack_size,
+            max_stack_size,
+            stack_size_text,
+            league,
+            id,
+            unidentified_tier,
+            influences,
+            elder,
+            shaper,
+            searing,
+            tangled,
+            memory_item,
+            abyss_jewel,
+            delve,
+            fractured,
+            synthesised,
+            sockets: CamelCase(sockets),
+            socketed_items: CamelCase(socketed_items),
+            name,
+            type_line,
+            base_type,
+            rarity,
+            identified: *identified,
+            item_level,
+            ilvl: *ilvl,
+            note,
+            forum_note,
+            locked_to_character,
+            locked_to_account,
+            duplicated,
+            split,
+            corrupted,
+            unmodifiable,
+            cis_race_reward,
+            sea_race_reward,
+            th_race_reward,
+            properties: CamelCase(properties),
+            notable_properties: CamelCase(notable_properties),
+            requirements: CamelCase(requirements),
+            weapon_properties: CamelCase(weapon_properties),
+            support_gem_requirements: CamelCase(support_gem_requirements),
+            additional_requirements: CamelCase(additional_requirements),
+            next_level_requirements: CamelCase(next_level_requirements),
+            granted_skills: CamelCase(granted_skills),
+            talisman_tier,
+            rewards,
+            sec_descr_text,
+            utility_mods,
+            logbook_mods,
+            enchant_mods,
+            rune_mods,
+            scourge_mods,
+            implicit_mods,
+            ultimatum_mods,
+            explicit_mods,
+            crafted_mods,
+            fractured_mods,
+            crucible_mods,
+            cosmetic_mods,
+            veiled_mods,
+            veiled,
+            descr_text,
+            flavour_text,
+            flavour_text_parsed,
+            flavour_text_note,
+            prophecy_text,
+            is_relic,
+            foil_variation,
+            replica,
+            foreseeing,
+            incubated_item,
+            scourged,
+            crucible: CamelCase(crucible),
+            ruthless,
+            frame_type,
+            art_filename,
+            hybrid: CamelCase(hybrid),
+            extended,
+            x,
+            y,
+            inventory_id,
+            socket,
+            colour,
+            gem_sockets,
+            gem_tabs: CamelCase(gem_tabs),
+            gem_background,
+            gem_skill,
+            extra,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CamelCaseItemSocket<'a> {
+    group: u8,
+    attr: &'a Option<String>,
+    s_colour: &'a Option<String>,
+    #[serde(rename = "type")]
+    socket_type: &'a Option<String>,
+    item: &'a Option<String>,
+}
+
+impl SerializeCamelCase for ItemSocket {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let ItemSocket {
+            group,
+            attr,
+            s_colour,
+            socket_type,
+            item,
+        } = self;
+
+        CamelCaseItemSocket {
+            group: *group,
+            attr,
+            s_colour,
+            socket_type,
+            item,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CamelCaseItemProperty<'a> {
+    name: &'a str,
+    values: &'a [(String, u8)],
+    display_mode: &'a Option<u8>,
+    progress: &'a Option<f32>,
+    #[serde(rename = "type")]
+    property_type: &'a Option<u32>,
+    suffix: &'a Option<String>,
+    icon: &'a Option<String>,
+}
+
+impl SerializeCamelCase for ItemProperty {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let ItemProperty {
+            name,
+            values,
+            display_mode,
+            progress,
+            property_type,
+            suffix,
+            icon,
+        } = self;
+
+        CamelCaseItemProperty {
+            name,
+            values,
+            display_mode,
+            progress,
+            property_type,
+            suffix,
+            icon,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+struct CamelCaseGemTab<'a> {
+    name: &'a Option<String>,
+    pages: CamelCase<'a, [GemPage]>,
+}
+
+impl SerializeCamelCase for GemTab {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let GemTab { name, pages } = self;
+
+        CamelCaseGemTab {
+            name,
+            pages: CamelCase(pages),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CamelCaseGemPage<'a> {
+    skill_name: &'a Option<String>,
+    description: &'a Option<String>,
+    properties: CamelCase<'a, Option<Vec<ItemProperty>>>,
+    stats: &'a Option<Vec<String>>,
+}
+
+impl SerializeCamelCase for GemPage {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let GemPage {
+            skill_name,
+            description,
+            properties,
+            stats,
+        } = self;
+
+        CamelCaseGemPage {
+            skill_name,
+            description,
+            properties: CamelCase(properties),
+            stats,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+struct CamelCaseCrucibleItem<'a> {
+    layout: &'a str,
+    nodes: CamelCase<'a, HashMap<String, CrucibleNode>>,
+}
+
+impl SerializeCamelCase for CrucibleItem {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let CrucibleItem { layout, nodes } = self;
+
+        CamelCaseCrucibleItem {
+            layout,
+            nodes: CamelCase(nodes),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CamelCaseCrucibleNode<'a> {
+    skill: &'a Option<u32>,
+    tier: &'a Option<u32>,
+    icon: &'a Option<String>,
+    allocated: &'a Option<bool>,
+    is_notable: &'a Option<bool>,
+    is_reward: &'a Option<bool>,
+    stats: &'a Option<Vec<String>>,
+    reminder_text: &'a Option<Vec<String>>,
+    orbit: &'a Option<u32>,
+    orbit_index: &'a Option<u32>,
+    out: &'a [String],
+    #[serde(rename = "in")]
+    incoming: &'a [String],
+}
+
+impl SerializeCamelCase for CrucibleNode {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let CrucibleNode {
+            skill,
+            tier,
+            icon,
+            allocated,
+            is_notable,
+            is_reward,
+            stats,
+            reminder_text,
+            orbit,
+            orbit_index,
+            out,
+            incoming,
+        } = self;
+
+        CamelCaseCrucibleNode {
+            skill,
+            tier,
+            icon,
+            allocated,
+            is_notable,
+            is_reward,
+            stats,
+            reminder_text,
+            orbit,
+            orbit_index,
+            out,
+            incoming,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CamelCaseHybridItem<'a> {
+    is_vaal_gem: &'a Option<bool>,
+    base_type_name: &'a str,
+    properties: CamelCase<'a, Option<Vec<ItemProperty>>>,
+    explicit_mods: &'a Option<Vec<String>>,
+    sec_descr_text: &'a Option<String>,
+}
+
+impl SerializeCamelCase for HybridItem {
+    fn serialize_camel_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let HybridItem {
+            is_vaal_gem,
+            base_type_name,
+            properties,
+            explicit_mods,
+            sec_descr_text,
+        } = self;
+
+        CamelCaseHybridItem {
+            is_vaal_gem,
+            base_type_name,
+            properties: CamelCase(properties),
+            explicit_mods,
+            sec_descr_text,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::OutputSchema;
+    use crate::{
+        common::stash::Stash,
+        poe_api::poe_stash_api::protocol::{Item, RejectedItem},
+    };
+
+    fn stash(item: serde_json::Value) -> Stash {
+        Stash {
+            id: "a".into(),
+            public: true,
+            account_name: None,
+            stash: None,
+            stash_type: "PremiumStash".into(),
+            items: vec![serde_json::from_value::<Item>(item).unwrap()],
+            rejected_items: vec![],
+            league: None,
+            realm: Default::default(),
+            created_at: Default::default(),
+            change_id: "1".into(),
+            next_change_id: "2".into(),
+            extra: [("new_stash_field".to_string(), json!(1))]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_camel_case() {
+        let stash = stash(json!({
+            "verified": false, "w": 1, "h": 1, "icon": "", "name": "", "typeLine": "Orb",
+            "baseType": "Orb", "identified": true, "ilvl": 0, "weaponRequirements": [],
+            "sockets": [{"group": 0, "sColour": "R"}],
+            "rewards": [{"label": "", "rewards": {"Exalted Orb": 2}}],
+            "crucible": {"layout": "", "nodes": {"0": {"isNotable": true, "out": [], "in": []}}},
+            "new_field": {"some_key": 1},
+        }));
+
+        let value = OutputSchema::CamelCase.to_value(&stash).unwrap();
+        let item = &value["items"][0];
+        assert_eq!(value["stashType"], "PremiumStash");
+        assert_eq!(value["new_stash_field"], 1);
+        assert_eq!(item["typeLine"], "Orb");
+        assert_eq!(item["weaponRequirements"], json!([]));
+        assert_eq!(item["sockets"][0]["sColour"], "R");
+        assert_eq!(item["rewards"][0]["rewards"], json!({"Exalted Orb": 2}));
+        assert_eq!(item["crucible"]["nodes"]["0"]["isNotable"], true);
+        // Fields that are not modelled are passed through as they are
+        assert_eq!(item["new_field"], json!({"some_key": 1}));
+
+        let value = OutputSchema::SnakeCase.to_value(&stash).unwrap();
+        assert_eq!(value["stash_type"], "PremiumStash");
+        assert_eq!(value["new_stash_field"], 1);
+        assert_eq!(value["items"][0]["type_line"], "Orb");
+        assert_eq!(serde_json::from_value::<Stash>(value).unwrap(), stash);
+        assert_eq!("camelCase".parse(), Ok(OutputSchema::CamelCase));
+    }
+
+    #[test]
+    fn test_camel_case_round_trip() {
+        let stash = stash(json!({
+            "verified": false, "w": 1, "h": 1, "icon": "", "name": "", "typeLine": "Orb",
+            "baseType": "Orb", "identified": true, "ilvl": 0, "stackSize": 3,
+            "weaponRequirements": [], "gemSockets": ["S"], "newField": 1,
+        }));
+
+        let serialized = OutputSchema::CamelCase.to_vec(&vec![&stash]).unwrap();
+        let value = serde_json::from_slice::<serde_json::Value>(&serialized).unwrap();
+        assert_eq!(value[0]["items"][0]["stackSize"], 3);
+        assert_eq!(value[0]["items"][0]["gemSockets"], json!(["S"]));
+        assert_eq!(
+            serde_json::from_slice::<Vec<Stash>>(&serialized).unwrap(),
+            vec![stash]
+        );
+    }
+
+    /// Every value in `value` is set, so the round trip in [`test_camel_case_every_field`]
+    /// covers every field
+    fn assert_no_nulls(path: &str, value: &serde_json::Value) {
+        match value {
+            serde_json::Value::Null => panic!("{path} is not set in the test fixture"),
+            serde_json::Value::Array(values) => values
+                .iter()
+                .for_each(|value| assert_no_nulls(&format!("{path}[]"), value)),
+            serde_json::Value::Object(object) => object
+                .iter()
+                .for_each(|(key, value)| assert_no_nulls(&format!("{path}.{key}"), value)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_camel_case_every_field() {
+        // A single `json!` would exceed the recursion limit
+        let property = r#"{"name": "Quality", "values": [["+20%", 1]], "displayMode": 0,
+            "progress": 0.5, "type": 6, "suffix": "", "icon": ""}"#;
+        let item = r#"{
+            "realm": "poe2", "verified": true, "w": 1, "h": 1, "icon": "", "support": true,
+            "stackSize": 1, "maxStackSize": 10, "stackSizeText": "1", "league": "Standard",
+            "id": "a", "unidentifiedTier": 1, "influences": {"shaper": true}, "elder": true,
+            "shaper": true, "searing": true, "tangled": true, "memoryItem": true,
+            "abyssJewel": true, "delve": true, "fractured": true, "synthesised": true,
+            "sockets": [{"group": 0, "attr": "S", "sColour": "R", "type": "gem", "item": "gem"}],
+            "socketedItems": [], "name": "", "typeLine": "Orb", "baseType": "Orb",
+            "rarity": "Rare", "identified": true, "itemLevel": 1, "ilvl": 1, "note": "",
+            "forumNote": "", "lockedToCharacter": true, "lockedToAccount": true,
+            "duplicated": true, "split": true, "corrupted": true, "unmodifiable": true,
+            "cisRaceReward": true, "seaRaceReward": true, "thRaceReward": true,
+            "properties": [PROPERTY], "notableProperties": [PROPERTY],
+            "requirements": [PROPERTY], "weaponRequirements": [PROPERTY],
+            "supportGemRequirements": [PROPERTY], "additionalRequirements": [PROPERTY],
+            "nextLevelRequirements": [PROPERTY], "grantedSkills": [PROPERTY],
+            "talismanTier": 1, "rewards": [{"label": "", "rewards": {"Exalted Orb": 1}}],
+            "secDescrText": "", "utilityMods": [""],
+            "logbookMods": [{"name": "", "faction": {"id": "Faction1", "name": ""}, "mods": [""]}],
+            "enchantMods": [""], "runeMods": [""], "scourgeMods": [""], "implicitMods": [""],
+            "ultimatumMods": [{"type": "", "tier": 1}], "explicitMods": [""],
+            "craftedMods": [""], "fracturedMods": [""], "crucibleMods": [""],
+            "cosmeticMods": [""], "veiledMods": [""], "veiled": true, "descrText": "",
+            "flavourText": [""], "flavourTextParsed": [""], "flavourTextNote": "",
+            "prophecyText": "", "isRelic": true, "foilVariation": 1, "replica": true,
+            "foreseeing": true,
+            "incubatedItem": {"name": "", "level": 1, "progress": 1, "total": 1},
+            "scourged": {"tier": 1, "level": 1, "progress": 1, "total": 1},
+            "crucible": {"layout": "", "nodes": {"0": {
+                "skill": 1, "tier": 1, "icon": "", "allocated": true, "isNotable": true,
+                "isReward": true, "stats": [""], "reminderText": [""], "orbit": 1,
+                "orbitIndex": 1, "out": ["1"], "in": ["2"]
+            }}},
+            "ruthless": true, "frameType": 1, "artFilename": "",
+            "hybrid": {
+                "isVaalGem": true, "baseTypeName": "", "properties": [PROPERTY],
+                "explicitMods": [""], "secDescrText": ""
+            },
+            "extended": {"prefixes": 1, "suffixes": 1}, "x": 1, "y": 1, "inventoryId": "",
+            "socket": 1, "colour": "S", "gemSockets": [""],
+            "gemTabs": [{"name": "", "pages": [{
+                "skillName": "", "description": "", "properties": [PROPERTY], "stats": [""]
+            }]}],
+            "gemBackground": "", "gemSkill": "", "newField": 1
+        }"#
+        .replace("PROPERTY", property);
+        let item = serde_json::from_str::<serde_json::Value>(&item).unwrap();
+        let mut stash = stash(item.clone());
+        stash.account_name = Some("foo".into());
+        stash.stash = Some("~price 1 chaos".into());
+        stash.league = Some("Standard".into());
+        stash.rejected_items = vec![RejectedItem {
+            item: json!({"ilvl": -1}),
+            reason: "invalid".into(),
+        }];
+        let mut socketed = stash.items[0].clone();
+        socketed.socketed_items = Some(vec![]);
+        stash.items[0].socketed_items = Some(vec![socketed]);
+
+        assert_no_nulls("stash", &OutputSchema::SnakeCase.to_value(&stash).unwrap());
+
+        // Fails for fields that are missing from their mirror struct or misnamed in it
+        let value = OutputSchema::CamelCase.to_value(&stash).unwrap();
+        let mut expected = item.clone();
+        expected["socketedItems"] = json!([item]);
+        assert_eq!(value["items"][0], expected);
+        assert_eq!(serde_json::from_value::<Stash>(value).unwrap(), stash);
+    }
+}
@@ -1,18 +1,42 @@
 use chrono::NaiveDateTime;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::poe_api::poe_stash_api::protocol::Item;
+use serde_json::{Map, Value};
 
-#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+use super::realm::Realm;
+use crate::poe_api::poe_stash_api::protocol::{Item, RejectedItem};
+
+/// Serializes with snake_case names by default, see
+/// [`OutputSchema`](super::schema::OutputSchema) for GGG's camelCase names. Both are
+/// accepted when deserializing.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Stash {
     pub id: String,
     pub public: bool,
+    #[serde(alias = "accountName")]
     pub account_name: Option<String>,
     pub stash: Option<String>,
+    #[serde(alias = "stashType")]
     pub stash_type: String,
     pub items: Vec<Item>,
+    /// Items that did not match [`Item`], kept as they were sent by the API
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        alias = "rejectedItems"
+    )]
+    pub rejected_items: Vec<RejectedItem>,
     pub league: Option<String>,
+    /// Archives from before realms were supported are all [`Realm::Pc`]
+    #[serde(default)]
+    pub realm: Realm,
+    #[serde(alias = "createdAt")]
     pub created_at: NaiveDateTime,
+    #[serde(alias = "changeId")]
     pub change_id: String,
+    #[serde(alias = "nextChangeId")]
     pub next_change_id: String,
+    /// Fields of the stash that are not modelled (yet), kept at their original keys
+    #[serde(default, flatten)]
+    pub extra: Map<String, Value>,
 }
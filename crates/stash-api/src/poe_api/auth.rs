@@ -1,15 +1,102 @@
-use std::time::Duration;
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
 
 use serde::{Deserialize, Serialize};
-use trade_common::secret::SecretString;
+use tokio::sync::RwLock;
+use trade_common::{secret::SecretString, ClientWithMiddleware};
+
+pub const DEFAULT_OAUTH_BASE_URL: &str = "https://www.pathofexile.com";
+
+/// Refresh access tokens at the latest this long before they expire
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+/// How many freshly issued access tokens the API may reject in a row before we give up
+const MAX_TOKEN_REJECTIONS: u32 = 3;
 
 pub fn user_agent(client_id: &str, developer_mail: &str) -> String {
     format!("OAuth {client_id}/0.1 (contact: {developer_mail})")
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct OAuthResponse {
     pub access_token: String,
+    /// Lifetime of the access token in seconds, tokens without one never expire
+    pub expires_in: Option<u64>,
+    pub token_type: Option<String>,
+    pub scope: Option<String>,
+}
+
+/// The error body of the OAuth token endpoint according to RFC 6749
+#[derive(Debug, Deserialize)]
+struct OAuthErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum OAuthError {
+    /// The token endpoint could not be reached
+    Request(reqwest_middleware::Error),
+    /// The token endpoint rejected our request, ie. with `invalid_client`
+    Rejected {
+        status: u16,
+        error: String,
+        description: Option<String>,
+    },
+    /// The token endpoint responded with an unexpected status code
+    Status(u16),
+    /// The token endpoint responded with something that is not a token
+    Decode(serde_json::Error),
+    /// The API keeps rejecting freshly issued access tokens, ie. because the client
+    /// lacks the `service:psapi` scope
+    TokenRejected,
+}
+
+impl OAuthError {
+    /// Whether retrying with the same credentials is pointless
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            OAuthError::Rejected { status, .. } | OAuthError::Status(status) => {
+                (400..500).contains(status) && *status != 429
+            }
+            OAuthError::TokenRejected => true,
+            OAuthError::Request(_) | OAuthError::Decode(_) => false,
+        }
+    }
+}
+
+impl Display for OAuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OAuthError::Request(e) => write!(f, "OAuth token request failed: {e}"),
+            OAuthError::Rejected {
+                status,
+                error,
+                description,
+            } => write!(
+                f,
+                "OAuth token request rejected with {status} {error}: {}",
+                description.as_deref().unwrap_or_default()
+            ),
+            OAuthError::Status(status) => {
+                write!(f, "OAuth token request failed with status code {status}")
+            }
+            OAuthError::Decode(e) => write!(f, "Failed decoding OAuth token response: {e}"),
+            OAuthError::TokenRejected => f.write_str("API keeps rejecting fresh access tokens"),
+        }
+    }
+}
+
+impl std::error::Error for OAuthError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OAuthError::Request(e) => Some(e),
+            OAuthError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -21,32 +108,37 @@ pub struct OAuthRequestPayload {
 }
 
 impl OAuthRequestPayload {
-    pub fn new(client_id: String, client_secret: String) -> Self {
+    pub fn new(client_id: String, client_secret: String, scope: String) -> Self {
         Self {
             client_id,
             client_secret,
             grant_type: "client_credentials".into(),
-            scope: "service:psapi".into(),
+            scope,
         }
     }
 }
 
 /// According to https://www.pathofexile.com/developer/docs/authorization
+///
+/// `base_url` is usually [`DEFAULT_OAUTH_BASE_URL`], but can point to any server that
+/// implements the `/oauth/token` endpoint, ie. a local mock.
 pub async fn get_oauth_token(
+    client: &ClientWithMiddleware,
+    base_url: &str,
     client_id: &str,
     client_secret: &SecretString,
     developer_mail: &SecretString,
-) -> Result<OAuthResponse, Box<dyn std::error::Error>> {
-    use trade_common::telemetry::generate_http_client;
-
-    let url = "https://www.pathofexile.com/oauth/token";
+    scope: &str,
+) -> Result<OAuthResponse, OAuthError> {
+    let url = format!("{}/oauth/token", base_url.trim_end_matches('/'));
     let payload = serde_urlencoded::to_string(OAuthRequestPayload::new(
         client_id.into(),
         client_secret.expose().to_string(),
+        scope.into(),
     ))
     .unwrap();
 
-    let response = generate_http_client(None)
+    let response = client
         .post(url)
         .header("Content-Type", "application/x-www-form-urlencoded")
         .header(
@@ -55,23 +147,141 @@ pub async fn get_oauth_token(
         )
         .body(payload)
         .send()
-        .await?;
+        .await
+        .map_err(OAuthError::Request)?;
 
-    serde_json::from_slice(&response.bytes().await?).map_err(|e| e.into())
+    let status = response.status();
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| OAuthError::Request(e.into()))?;
+
+    if !status.is_success() {
+        return Err(match serde_json::from_slice::<OAuthErrorResponse>(&body) {
+            Ok(e) => OAuthError::Rejected {
+                status: status.as_u16(),
+                error: e.error,
+                description: e.error_description,
+            },
+            Err(_) => OAuthError::Status(status.as_u16()),
+        });
+    }
+
+    serde_json::from_slice(&body).map_err(OAuthError::Decode)
 }
 
-const DEFAULT_RATE_LIMIT_TIMER: u64 = 60;
+/// Caches a client credentials access token and transparently refreshes it once it is
+/// about to expire or got rejected by the API.
+#[derive(Debug)]
+pub(crate) struct OAuthCredentials {
+    client: ClientWithMiddleware,
+    base_url: String,
+    client_id: String,
+    client_secret: SecretString,
+    developer_mail: SecretString,
+    scope: String,
+    current: RwLock<Option<CachedToken>>,
+    rejections: AtomicU32,
+}
 
-pub fn parse_rate_limit_timer(input: Option<&str>) -> Duration {
-    let seconds = input
-        .and_then(|v| v.split(':').next_back())
-        .map(|s| {
-            if s.ne("60") {
-                tracing::warn!("Expected x-rate-limit-ip to be 60 seconds");
+#[derive(Debug)]
+struct CachedToken {
+    access_token: String,
+    refresh_at: Option<Instant>,
+}
+
+impl CachedToken {
+    fn new(response: OAuthResponse) -> Self {
+        let refresh_at = response
+            .expires_in
+            .map(Duration::from_secs)
+            .map(|lifetime| {
+                // Leave some headroom for short-lived tokens too
+                Instant::now() + lifetime.saturating_sub(REFRESH_MARGIN.min(lifetime / 5))
+            });
+
+        Self {
+            access_token: response.access_token,
+            refresh_at,
+        }
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.refresh_at.is_none_or(|at| Instant::now() < at)
+    }
+}
+
+impl OAuthCredentials {
+    pub(crate) fn new(
+        client: ClientWithMiddleware,
+        base_url: String,
+        client_id: String,
+        client_secret: SecretString,
+        developer_mail: SecretString,
+        scope: String,
+    ) -> Self {
+        Self {
+            client,
+            base_url,
+            client_id,
+            client_secret,
+            developer_mail,
+            scope,
+            current: RwLock::new(None),
+            rejections: AtomicU32::new(0),
+        }
+    }
+
+    /// Returns a valid access token, fetching a new one if necessary
+    pub(crate) async fn access_token(&self) -> Result<String, OAuthError> {
+        if let Some(token) = self.current.read().await.as_ref().filter(|t| t.is_fresh()) {
+            return Ok(token.access_token.clone());
+        }
+
+        let mut current = self.current.write().await;
+        // Another job might have refreshed the token while we waited for the lock
+        if let Some(token) = current.as_ref().filter(|t| t.is_fresh()) {
+            return Ok(token.access_token.clone());
+        }
+
+        tracing::info!("Fetching new OAuth access token");
+        let response = get_oauth_token(
+            &self.client,
+            &self.base_url,
+            &self.client_id,
+            &self.client_secret,
+            &self.developer_mail,
+            &self.scope,
+        )
+        .await?;
+        let token = CachedToken::new(response);
+        let access_token = token.access_token.clone();
+        current.replace(token);
+
+        Ok(access_token)
+    }
+
+    /// Discards `access_token` after the API rejected it, so the next call to
+    /// [`Self::access_token`] fetches a new one.
+    ///
+    /// Fails once the API rejected too many tokens in a row.
+    pub(crate) async fn reject(&self, access_token: &str) -> Result<(), OAuthError> {
+        let mut current = self.current.write().await;
+        if current
+            .as_ref()
+            .is_some_and(|t| t.access_token == access_token)
+        {
+            current.take();
+            if self.rejections.fetch_add(1, Ordering::Relaxed) + 1 >= MAX_TOKEN_REJECTIONS {
+                return Err(OAuthError::TokenRejected);
             }
-            s.parse().unwrap_or(DEFAULT_RATE_LIMIT_TIMER)
-        })
-        .unwrap_or(DEFAULT_RATE_LIMIT_TIMER);
+        }
 
-    Duration::from_secs(seconds)
+        Ok(())
+    }
+
+    /// Marks the current access token as accepted by the API
+    pub(crate) fn accept(&self) {
+        self.rejections.store(0, Ordering::Relaxed);
+    }
 }
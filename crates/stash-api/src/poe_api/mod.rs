@@ -1,2 +1,3 @@
 pub mod auth;
 pub mod poe_stash_api;
+pub mod rate_limit;
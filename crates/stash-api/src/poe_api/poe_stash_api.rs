@@ -1,6 +1,9 @@
 /// Internal types that model what the data coming from PoE's API looks like.
 pub mod protocol {
+    use std::collections::HashMap;
+
     use serde::{Deserialize, Serialize};
+    use serde_json::{Map, Value};
 
     /// The official API schema for the Publish Stash Tab API provided by GGG.
     ///
@@ -8,16 +11,20 @@ pub mod protocol {
     ///
     /// Every individual API response refers to a specific change id and holds a list of
     /// stashes with their latest contents.
-    #[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+    #[derive(Debug, Deserialize, Clone, PartialEq)]
     pub struct PublicStashTabResponse {
         pub next_change_id: String,
         pub stashes: Vec<PublicStashChange>,
     }
 
-    #[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+    #[derive(Debug, Deserialize, Clone, PartialEq)]
+    #[serde(from = "RawPublicStashChange")]
     /// The official API schema for the changes of a single stash.
     ///
     /// See https://www.pathofexile.com/developer/docs/reference#type-PublicStashChange
+    ///
+    /// Items are deserialized one by one, so a single item that does not match [`Item`]
+    /// ends up in `rejected_items` instead of failing the whole page.
     pub struct PublicStashChange {
         /// A unique 64 digit hexadecimal string
         pub id: String,
@@ -30,9 +37,65 @@ pub mod protocol {
         pub stash_type: String,
         pub league: Option<String>,
         pub items: Vec<Item>,
+        pub rejected_items: Vec<RejectedItem>,
+        /// Fields that are not modelled (yet)
+        pub extra: Map<String, Value>,
+    }
+
+    #[derive(Deserialize)]
+    struct RawPublicStashChange {
+        id: String,
+        public: bool,
+        #[serde(rename = "accountName")]
+        account_name: Option<String>,
+        stash: Option<String>,
+        #[serde(rename = "stashType")]
+        stash_type: String,
+        league: Option<String>,
+        items: Vec<Value>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
     }
 
-    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+    impl From<RawPublicStashChange> for PublicStashChange {
+        fn from(raw: RawPublicStashChange) -> Self {
+            let mut items = Vec::with_capacity(raw.items.len());
+            let mut rejected_items = vec![];
+            for item in raw.items {
+                match Item::deserialize(&item) {
+                    Ok(parsed) => items.push(parsed),
+                    Err(e) => rejected_items.push(RejectedItem {
+                        reason: e.to_string(),
+                        item,
+                    }),
+                }
+            }
+
+            Self {
+                id: raw.id,
+                public: raw.public,
+                account_name: raw.account_name,
+                stash: raw.stash,
+                stash_type: raw.stash_type,
+                league: raw.league,
+                items,
+                rejected_items,
+                extra: raw.extra,
+            }
+        }
+    }
+
+    /// An item that failed to deserialize into an [`Item`], as it was sent by the API
+    #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+    pub struct RejectedItem {
+        pub item: Value,
+        pub reason: String,
+    }
+
+    /// Serializes with snake_case names, which are accepted when deserializing as well, so
+    /// items that we archived ourselves can be read back in. Fields that are not modelled
+    /// (yet) are kept as they are in `extra`.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
     pub struct Item {
         /// Always `poe2` if present
         pub realm: Option<String>,
@@ -41,148 +104,202 @@ pub mod protocol {
         pub h: u8,
         pub icon: String,
         pub support: Option<bool>,
-        #[serde(rename(deserialize = "stackSize"))]
+        #[serde(rename(deserialize = "stackSize"), alias = "stack_size")]
         pub stack_size: Option<u16>,
-        #[serde(rename(deserialize = "maxStackSize"))]
+        #[serde(rename(deserialize = "maxStackSize"), alias = "max_stack_size")]
         pub max_stack_size: Option<u16>,
-        #[serde(rename(deserialize = "stackSizeText"))]
+        #[serde(rename(deserialize = "stackSizeText"), alias = "stack_size_text")]
         pub stack_size_text: Option<String>,
         pub league: Option<String>,
         pub id: Option<String>,
-        #[serde(rename(deserialize = "unidentifiedTier"))]
+        #[serde(rename(deserialize = "unidentifiedTier"), alias = "unidentified_tier")]
         pub unidentified_tier: Option<u8>,
         pub influences: Option<serde_json::Value>,
         pub elder: Option<bool>,
         pub shaper: Option<bool>,
         pub searing: Option<bool>,
         pub tangled: Option<bool>,
-        #[serde(rename(deserialize = "memoryItem"))]
+        #[serde(rename(deserialize = "memoryItem"), alias = "memory_item")]
         pub memory_item: Option<bool>,
-        #[serde(rename(deserialize = "abyssJewel"))]
+        #[serde(rename(deserialize = "abyssJewel"), alias = "abyss_jewel")]
         pub abyss_jewel: Option<bool>,
         pub delve: Option<bool>,
         pub fractured: Option<bool>,
         pub synthesised: Option<bool>,
         pub sockets: Option<Vec<ItemSocket>>,
-        #[serde(rename(deserialize = "socketedItems"))]
+        #[serde(rename(deserialize = "socketedItems"), alias = "socketed_items")]
         pub socketed_items: Option<Vec<Item>>,
         pub name: String,
-        #[serde(rename(deserialize = "typeLine"))]
+        #[serde(rename(deserialize = "typeLine"), alias = "type_line")]
         pub type_line: String,
-        #[serde(rename(deserialize = "baseType"))]
+        #[serde(rename(deserialize = "baseType"), alias = "base_type")]
         pub base_type: String,
         /// Normal, Magic, Rare, or Unique
         pub rarity: Option<String>,
         pub identified: bool,
-        #[serde(rename(deserialize = "itemLevel"))]
+        #[serde(rename(deserialize = "itemLevel"), alias = "item_level")]
         pub item_level: Option<u8>,
         pub ilvl: u8,
         pub note: Option<String>,
-        #[serde(rename(deserialize = "forumNote"))]
+        #[serde(rename(deserialize = "forumNote"), alias = "forum_note")]
         pub forum_note: Option<String>,
-        #[serde(rename(deserialize = "lockedToCharacter"))]
+        #[serde(
+            rename(deserialize = "lockedToCharacter"),
+            alias = "locked_to_character"
+        )]
         pub locked_to_character: Option<bool>,
-        #[serde(rename(deserialize = "lockedToAccount"))]
+        #[serde(rename(deserialize = "lockedToAccount"), alias = "locked_to_account")]
         pub locked_to_account: Option<bool>,
         pub duplicated: Option<bool>,
         pub split: Option<bool>,
         pub corrupted: Option<bool>,
         pub unmodifiable: Option<bool>,
-        #[serde(rename(deserialize = "cisRaceReward"))]
+        #[serde(rename(deserialize = "cisRaceReward"), alias = "cis_race_reward")]
         pub cis_race_reward: Option<bool>,
-        #[serde(rename(deserialize = "seaRaceReward"))]
+        #[serde(rename(deserialize = "seaRaceReward"), alias = "sea_race_reward")]
         pub sea_race_reward: Option<bool>,
-        #[serde(rename(deserialize = "thRaceReward"))]
+        #[serde(rename(deserialize = "thRaceReward"), alias = "th_race_reward")]
         pub th_race_reward: Option<bool>,
         pub properties: Option<Vec<ItemProperty>>,
-        #[serde(rename(deserialize = "notableProperties"))]
+        #[serde(
+            rename(deserialize = "notableProperties"),
+            alias = "notable_properties"
+        )]
         pub notable_properties: Option<Vec<ItemProperty>>,
         pub requirements: Option<Vec<ItemProperty>>,
-        #[serde(rename(deserialize = "weaponRequirements"))]
+        #[serde(
+            rename(deserialize = "weaponRequirements"),
+            alias = "weapon_properties"
+        )]
         pub weapon_properties: Option<Vec<ItemProperty>>,
-        #[serde(rename(deserialize = "supportGemRequirements"))]
+        #[serde(
+            rename(deserialize = "supportGemRequirements"),
+            alias = "support_gem_requirements"
+        )]
         pub support_gem_requirements: Option<Vec<ItemProperty>>,
-        #[serde(rename(deserialize = "additionalRequirements"))]
+        #[serde(
+            rename(deserialize = "additionalRequirements"),
+            alias = "additional_requirements"
+        )]
         pub additional_requirements: Option<Vec<ItemProperty>>,
-        #[serde(rename(deserialize = "nextLevelRequirements"))]
+        #[serde(
+            rename(deserialize = "nextLevelRequirements"),
+            alias = "next_level_requirements"
+        )]
         pub next_level_requirements: Option<Vec<ItemProperty>>,
-        #[serde(rename(deserialize = "grantedSkills"))]
+        #[serde(rename(deserialize = "grantedSkills"), alias = "granted_skills")]
         pub granted_skills: Option<Vec<ItemProperty>>,
-        #[serde(rename(deserialize = "talismanTier"))]
+        #[serde(rename(deserialize = "talismanTier"), alias = "talisman_tier")]
         pub talisman_tier: Option<u8>,
         pub rewards: Option<Vec<ItemReward>>,
-        #[serde(rename(deserialize = "secDescrText"))]
+        #[serde(rename(deserialize = "secDescrText"), alias = "sec_descr_text")]
         pub sec_descr_text: Option<String>,
-        #[serde(rename(deserialize = "utilityMods"))]
+        #[serde(rename(deserialize = "utilityMods"), alias = "utility_mods")]
         pub utility_mods: Option<Vec<String>>,
-        #[serde(rename(deserialize = "logbookMods"))]
+        #[serde(rename(deserialize = "logbookMods"), alias = "logbook_mods")]
         pub logbook_mods: Option<Vec<LogbookMods>>,
-        #[serde(rename(deserialize = "enchantMods"))]
+        #[serde(rename(deserialize = "enchantMods"), alias = "enchant_mods")]
         pub enchant_mods: Option<Vec<String>>,
-        #[serde(rename(deserialize = "runeMods"))]
+        #[serde(rename(deserialize = "runeMods"), alias = "rune_mods")]
         pub rune_mods: Option<Vec<String>>,
-        #[serde(rename(deserialize = "scourgeMods"))]
+        #[serde(rename(deserialize = "scourgeMods"), alias = "scourge_mods")]
         pub scourge_mods: Option<Vec<String>>,
-        #[serde(rename(deserialize = "implicitMods"))]
+        #[serde(rename(deserialize = "implicitMods"), alias = "implicit_mods")]
         pub implicit_mods: Option<Vec<String>>,
-        #[serde(rename(deserialize = "ultimatumMods"))]
+        #[serde(rename(deserialize = "ultimatumMods"), alias = "ultimatum_mods")]
         pub ultimatum_mods: Option<Vec<UltimatumMod>>,
-        #[serde(rename(deserialize = "explicitMods"))]
+        #[serde(rename(deserialize = "explicitMods"), alias = "explicit_mods")]
         pub explicit_mods: Option<Vec<String>>,
-        #[serde(rename(deserialize = "craftedMods"))]
+        #[serde(rename(deserialize = "craftedMods"), alias = "crafted_mods")]
         pub crafted_mods: Option<Vec<String>>,
-        #[serde(rename(deserialize = "fracturedMods"))]
+        #[serde(rename(deserialize = "fracturedMods"), alias = "fractured_mods")]
         pub fractured_mods: Option<Vec<String>>,
-        #[serde(rename(deserialize = "crucibleMods"))]
+        #[serde(rename(deserialize = "crucibleMods"), alias = "crucible_mods")]
         pub crucible_mods: Option<Vec<String>>,
-        #[serde(rename(deserialize = "cosmeticMods"))]
+        #[serde(rename(deserialize = "cosmeticMods"), alias = "cosmetic_mods")]
         pub cosmetic_mods: Option<Vec<String>>,
-        #[serde(rename(deserialize = "veiledMods"))]
+        #[serde(rename(deserialize = "veiledMods"), alias = "veiled_mods")]
         pub veiled_mods: Option<Vec<String>>,
         pub veiled: Option<bool>,
-        #[serde(rename(deserialize = "descrText"))]
+        #[serde(rename(deserialize = "descrText"), alias = "descr_text")]
         pub descr_text: Option<String>,
-        #[serde(rename(deserialize = "flavourText"))]
+        #[serde(rename(deserialize = "flavourText"), alias = "flavour_text")]
         pub flavour_text: Option<Vec<String>>,
-        #[serde(rename(deserialize = "flavourTextParsed"))]
+        #[serde(
+            rename(deserialize = "flavourTextParsed"),
+            alias = "flavour_text_parsed"
+        )]
         pub flavour_text_parsed: Option<Vec<String>>,
-        #[serde(rename(deserialize = "flavourTextNote"))]
+        #[serde(rename(deserialize = "flavourTextNote"), alias = "flavour_text_note")]
         pub flavour_text_note: Option<String>,
-        #[serde(rename(deserialize = "prophecyText"))]
+        #[serde(rename(deserialize = "prophecyText"), alias = "prophecy_text")]
         pub prophecy_text: Option<String>,
-        #[serde(rename(deserialize = "isRelic"))]
+        #[serde(rename(deserialize = "isRelic"), alias = "is_relic")]
         pub is_relic: Option<bool>,
-        #[serde(rename(deserialize = "foilVariation"))]
+        #[serde(rename(deserialize = "foilVariation"), alias = "foil_variation")]
         pub foil_variation: Option<u8>,
         pub replica: Option<bool>,
         pub foreseeing: Option<bool>,
-        #[serde(rename(deserialize = "incubatedItem"))]
+        #[serde(rename(deserialize = "incubatedItem"), alias = "incubated_item")]
         pub incubated_item: Option<IncubatedItem>,
         pub scourged: Option<ScourgedItem>,
         pub crucible: Option<CrucibleItem>,
         pub ruthless: Option<bool>,
-        #[serde(rename(deserialize = "frameType"))]
+        #[serde(rename(deserialize = "frameType"), alias = "frame_type")]
         pub frame_type: Option<u8>,
-        #[serde(rename(deserialize = "artFilename"))]
+        #[serde(rename(deserialize = "artFilename"), alias = "art_filename")]
         pub art_filename: Option<String>,
         pub hybrid: Option<HybridItem>,
         pub extended: Option<ItemExtendedProp>,
         pub x: Option<u8>,
         pub y: Option<u8>,
-        #[serde(rename(deserialize = "inventoryId"))]
+        #[serde(rename(deserialize = "inventoryId"), alias = "inventory_id")]
         pub inventory_id: Option<String>,
         pub socket: Option<u8>,
         pub colour: Option<String>,
 
         /// PoE 2 only - not yet filled
-        #[serde(rename(deserialize = "getSockets"))]
+        #[serde(rename(deserialize = "gemSockets"), alias = "gem_sockets")]
         pub gem_sockets: Option<Vec<String>>,
-        #[serde(rename(deserialize = "gemTabs"))]
+        #[serde(rename(deserialize = "gemTabs"), alias = "gem_tabs")]
         pub gem_tabs: Option<Vec<GemTab>>,
-        #[serde(rename(deserialize = "gemBackground"))]
+        #[serde(rename(deserialize = "gemBackground"), alias = "gem_background")]
         pub gem_background: Option<String>,
-        #[serde(rename(deserialize = "gemSkill"))]
+        #[serde(rename(deserialize = "gemSkill"), alias = "gem_skill")]
         pub gem_skill: Option<String>,
+
+        #[serde(flatten, default, skip_serializing_if = "Map::is_empty")]
+        pub extra: Map<String, Value>,
+    }
+
+    impl Item {
+        /// The size of the largest group of linked sockets
+        pub fn links(&self) -> u8 {
+            let mut groups = HashMap::<u8, u8>::new();
+            for socket in self.sockets.iter().flatten() {
+                *groups.entry(socket.group).or_default() += 1;
+            }
+            groups.into_values().max().unwrap_or(0)
+        }
+
+        /// The property with the given name, ie. `Quality`
+        pub fn property(&self, name: &str) -> Option<&ItemProperty> {
+            self.properties.iter().flatten().find(|p| p.name == name)
+        }
+
+        pub fn quality(&self) -> Option<u8> {
+            self.property("Quality")?.numeric_value()
+        }
+
+        /// Only set for gems
+        pub fn gem_level(&self) -> Option<u8> {
+            self.property("Level")?.numeric_value()
+        }
+
+        /// Only set for maps
+        pub fn map_tier(&self) -> Option<u8> {
+            self.property("Map Tier")?.numeric_value()
+        }
     }
 
     #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
@@ -191,33 +308,231 @@ pub mod protocol {
         pub suffixes: Option<u8>,
     }
 
-    #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-    pub struct ItemSocket {}
+    /// See https://www.pathofexile.com/developer/docs/reference#type-ItemSocket
+    #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+    pub struct ItemSocket {
+        /// Sockets of the same group are linked
+        pub group: u8,
+        /// S, D, I, G, A or DV
+        pub attr: Option<String>,
+        /// R, G, B, W, A or DV
+        #[serde(rename(deserialize = "sColour"), alias = "s_colour")]
+        pub s_colour: Option<String>,
+        /// PoE 2 only - gem, jewel or rune
+        #[serde(rename = "type")]
+        pub socket_type: Option<String>,
+        /// PoE 2 only
+        pub item: Option<String>,
+    }
 
-    #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-    pub struct ItemProperty {}
+    /// A named property of an item, ie. its quality, map tier or gem level.
+    ///
+    /// See https://www.pathofexile.com/developer/docs/reference#type-ItemProperty
+    #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+    pub struct ItemProperty {
+        pub name: String,
+        /// Pairs of a displayed value and its display style
+        pub values: Vec<(String, u8)>,
+        #[serde(rename(deserialize = "displayMode"), alias = "display_mode")]
+        pub display_mode: Option<u8>,
+        /// Rounded to two decimal places
+        pub progress: Option<f32>,
+        #[serde(rename = "type")]
+        pub property_type: Option<u32>,
+        pub suffix: Option<String>,
+        pub icon: Option<String>,
+    }
+
+    impl ItemProperty {
+        /// The first value as a number, ie. `20` for a quality of `+20%`
+        pub fn numeric_value(&self) -> Option<u8> {
+            let (value, _) = self.values.first()?;
+            let digits = value
+                .trim_start_matches('+')
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>();
+            digits.parse().ok()
+        }
+    }
 
+    /// See https://www.pathofexile.com/developer/docs/reference#type-ItemReward
     #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-    pub struct ItemReward {}
+    pub struct ItemReward {
+        pub label: String,
+        /// Amount of each reward, keyed by the name of the reward
+        pub rewards: HashMap<String, u32>,
+    }
 
+    /// See https://www.pathofexile.com/developer/docs/reference#type-LogbookMod
     #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-    pub struct LogbookMods {}
+    pub struct LogbookMods {
+        /// The area name
+        pub name: String,
+        pub faction: LogbookFaction,
+        pub mods: Vec<String>,
+    }
 
     #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-    pub struct UltimatumMod {}
+    pub struct LogbookFaction {
+        /// Faction1, Faction2, Faction3 or Faction4
+        pub id: String,
+        pub name: String,
+    }
 
+    /// See https://www.pathofexile.com/developer/docs/reference#type-UltimatumMod
     #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-    pub struct GemTab {}
+    pub struct UltimatumMod {
+        /// Text used to display the mod
+        #[serde(rename = "type")]
+        pub mod_type: String,
+        pub tier: u8,
+    }
+
+    /// PoE 2 only
+    #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+    pub struct GemTab {
+        pub name: Option<String>,
+        pub pages: Vec<GemPage>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+    pub struct GemPage {
+        #[serde(rename(deserialize = "skillName"), alias = "skill_name")]
+        pub skill_name: Option<String>,
+        pub description: Option<String>,
+        pub properties: Option<Vec<ItemProperty>>,
+        pub stats: Option<Vec<String>>,
+    }
 
     #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-    pub struct IncubatedItem {}
+    pub struct IncubatedItem {
+        pub name: String,
+        /// Monster level required to progress
+        pub level: u8,
+        pub progress: u32,
+        pub total: u32,
+    }
 
     #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-    pub struct ScourgedItem {}
+    pub struct ScourgedItem {
+        /// 1 to 3 for items, 1 to 10 for maps
+        pub tier: u8,
+        /// Monster level required to progress
+        pub level: Option<u8>,
+        pub progress: Option<u32>,
+        pub total: Option<u32>,
+    }
 
     #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-    pub struct CrucibleItem {}
+    pub struct CrucibleItem {
+        /// URL to an image of the tree layout
+        pub layout: String,
+        /// Keyed by the string value of each node's index
+        pub nodes: HashMap<String, CrucibleNode>,
+    }
 
+    /// See https://www.pathofexile.com/developer/docs/reference#type-CrucibleNode
     #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-    pub struct HybridItem {}
+    pub struct CrucibleNode {
+        /// Mod hash
+        pub skill: Option<u32>,
+        /// Mod tier
+        pub tier: Option<u32>,
+        pub icon: Option<String>,
+        /// Always `true` if present
+        pub allocated: Option<bool>,
+        /// Always `true` if present
+        #[serde(rename(deserialize = "isNotable"), alias = "is_notable")]
+        pub is_notable: Option<bool>,
+        /// Always `true` if present
+        #[serde(rename(deserialize = "isReward"), alias = "is_reward")]
+        pub is_reward: Option<bool>,
+        /// Stat descriptions
+        pub stats: Option<Vec<String>>,
+        #[serde(rename(deserialize = "reminderText"), alias = "reminder_text")]
+        pub reminder_text: Option<Vec<String>>,
+        /// The column this node occupies
+        pub orbit: Option<u32>,
+        /// The node's position within the column
+        #[serde(rename(deserialize = "orbitIndex"), alias = "orbit_index")]
+        pub orbit_index: Option<u32>,
+        /// Node identifiers of nodes this one connects to
+        pub out: Vec<String>,
+        /// Node identifiers of nodes connected to this one
+        #[serde(rename = "in")]
+        pub incoming: Vec<String>,
+    }
+
+    /// The hybrid gem a Vaal or transfigured gem turns into, ie. its Vaal skill
+    #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+    pub struct HybridItem {
+        #[serde(rename(deserialize = "isVaalGem"), alias = "is_vaal_gem")]
+        pub is_vaal_gem: Option<bool>,
+        #[serde(rename(deserialize = "baseTypeName"), alias = "base_type_name")]
+        pub base_type_name: String,
+        pub properties: Option<Vec<ItemProperty>>,
+        #[serde(rename(deserialize = "explicitMods"), alias = "explicit_mods")]
+        pub explicit_mods: Option<Vec<String>>,
+        #[serde(rename(deserialize = "secDescrText"), alias = "sec_descr_text")]
+        pub sec_descr_text: Option<String>,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::protocol::{Item, PublicStashChange};
+
+    #[test]
+    fn test_reject_invalid_items() {
+        let item = |ilvl: &str| {
+            format!(
+                r#"{{"verified": false, "w": 1, "h": 1, "icon": "", "name": "", "typeLine": "Orb",
+                "baseType": "Orb", "identified": true, "ilvl": {ilvl}, "newField": [1]}}"#
+            )
+        };
+        let stash = format!(
+            r#"{{"id": "a", "public": true, "accountName": null, "stash": null,
+            "stashType": "PremiumStash", "league": null, "items": [{}, {}], "newStashField": 1}}"#,
+            item("1"),
+            item("-1")
+        );
+
+        let stash = serde_json::from_str::<PublicStashChange>(&stash).unwrap();
+
+        assert_eq!(stash.items.len(), 1);
+        assert_eq!(stash.items[0].extra["newField"], serde_json::json!([1]));
+        assert_eq!(stash.rejected_items.len(), 1);
+        assert_eq!(stash.rejected_items[0].item["ilvl"], -1);
+        assert_eq!(stash.extra["newStashField"], 1);
+
+        // Unknown fields survive archiving and reading the archive back in
+        let archived = serde_json::to_string(&stash.items[0]).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Item>(&archived).unwrap(),
+            stash.items[0]
+        );
+    }
+
+    #[test]
+    fn test_item_properties() {
+        let item = serde_json::from_str::<Item>(
+            r#"{"verified": false, "w": 1, "h": 1, "icon": "", "name": "", "typeLine": "Spark",
+            "baseType": "Spark", "identified": true, "ilvl": 0,
+            "sockets": [{"group": 0, "sColour": "R"}, {"group": 1, "sColour": "G"},
+                {"group": 1, "sColour": "B", "attr": "I"}],
+            "properties": [
+                {"name": "Level", "values": [["20 (Max)", 0]], "displayMode": 0, "type": 5},
+                {"name": "Quality", "values": [["+23%", 1]], "displayMode": 0, "type": 6}
+            ],
+            "hybrid": {"isVaalGem": true, "baseTypeName": "Vaal Spark"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(item.links(), 2);
+        assert_eq!(item.gem_level(), Some(20));
+        assert_eq!(item.quality(), Some(23));
+        assert_eq!(item.map_tier(), None);
+        assert_eq!(item.hybrid.unwrap().base_type_name, "Vaal Spark");
+    }
 }
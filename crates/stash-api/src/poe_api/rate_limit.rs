@@ -0,0 +1,270 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+/// The restriction we assume if GGG rate limits us without telling us for how long
+pub const DEFAULT_RATE_LIMIT_TIMER: Duration = Duration::from_secs(60);
+
+/// A single rule of a rate limit policy, ie. `45:60:60` in `X-Rate-Limit-Ip`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitRule {
+    pub max_hits: u32,
+    pub period: Duration,
+    /// How long we get restricted for when exceeding the rule
+    pub restriction: Duration,
+}
+
+/// The current state of a rule, ie. `1:60:0` in `X-Rate-Limit-Ip-State`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitRuleState {
+    pub hits: u32,
+    pub period: Duration,
+    /// How long we are still restricted for, zero if not restricted
+    pub active_restriction: Duration,
+}
+
+/// A rate limit policy as described by the `X-Rate-Limit-*` response headers.
+///
+/// See https://www.pathofexile.com/developer/docs/index#ratelimits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitPolicy {
+    pub name: String,
+    pub rules: Vec<(RateLimitRule, Option<RateLimitRuleState>)>,
+}
+
+impl RateLimitPolicy {
+    /// Parses `X-Rate-Limit-Policy`, `X-Rate-Limit-Rules` and all referenced
+    /// `X-Rate-Limit-{rule}` and `X-Rate-Limit-{rule}-State` headers
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+        let name = header("x-rate-limit-policy")?.to_string();
+        let rules = header("x-rate-limit-rules")?
+            .split(',')
+            .map(|rule| rule.trim().to_lowercase())
+            .filter(|rule| !rule.is_empty())
+            .flat_map(|rule| {
+                let limits = header(&format!("x-rate-limit-{rule}"))
+                    .map(parse_triples)
+                    .unwrap_or_default();
+                let states = header(&format!("x-rate-limit-{rule}-state"))
+                    .map(parse_triples)
+                    .unwrap_or_default();
+
+                limits
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, (max_hits, period, restriction))| {
+                        let rule = RateLimitRule {
+                            max_hits: max_hits as u32,
+                            period: Duration::from_secs(period),
+                            restriction: Duration::from_secs(restriction),
+                        };
+                        let state =
+                            states
+                                .get(idx)
+                                .map(|(hits, period, active)| RateLimitRuleState {
+                                    hits: *hits as u32,
+                                    period: Duration::from_secs(*period),
+                                    active_restriction: Duration::from_secs(*active),
+                                });
+                        (rule, state)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        Some(Self { name, rules })
+    }
+
+    /// The smallest interval between two requests that does not exceed any rule
+    pub fn request_interval(&self) -> Option<Duration> {
+        self.rules
+            .iter()
+            .filter(|(rule, _)| rule.max_hits > 0)
+            .map(|(rule, _)| rule.period / rule.max_hits)
+            .max()
+    }
+
+    /// The longest restriction that is currently active, if any
+    pub fn active_restriction(&self) -> Option<Duration> {
+        self.rules
+            .iter()
+            .filter_map(|(_, state)| state.map(|s| s.active_restriction))
+            .filter(|d| !d.is_zero())
+            .max()
+    }
+}
+
+/// Parses a comma-separated list of `a:b:c` triples
+fn parse_triples(input: &str) -> Vec<(u64, u64, u64)> {
+    input
+        .split(',')
+        .filter_map(|triple| {
+            let mut parts = triple.trim().split(':').map(|p| p.parse::<u64>().ok());
+            match (parts.next()??, parts.next()??, parts.next()??) {
+                (a, b, c) if parts.next().is_none() => Some((a, b, c)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Parses the `Retry-After` header, which GGG sends in seconds
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// A leaky bucket with a capacity of one request, whose interval adapts to the rate limit
+/// policy that GGG announces with every response.
+#[derive(Debug)]
+pub(crate) struct AdaptiveRateLimiter {
+    inner: Mutex<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    interval: Duration,
+    last_request: Option<Instant>,
+    restricted_until: Option<Instant>,
+}
+
+impl AdaptiveRateLimiter {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            inner: Mutex::new(LimiterState {
+                interval,
+                last_request: None,
+                restricted_until: None,
+            }),
+        }
+    }
+
+    /// Waits until the next request may be sent
+    pub(crate) async fn acquire(&self) {
+        loop {
+            let slot = {
+                let mut state = self.inner.lock().unwrap();
+                let now = Instant::now();
+                let earliest = [
+                    state.last_request.map(|last| last + state.interval),
+                    state.restricted_until,
+                ]
+                .into_iter()
+                .flatten()
+                .max();
+
+                match earliest {
+                    Some(slot) if slot > now => slot,
+                    _ => {
+                        state.last_request = Some(now);
+                        return;
+                    }
+                }
+            };
+
+            tokio::time::sleep_until(slot.into()).await;
+        }
+    }
+
+    /// Adjusts the request interval and honors active restrictions of `policy`
+    pub(crate) fn adapt(&self, policy: &RateLimitPolicy) {
+        if let Some(interval) = policy.request_interval() {
+            let mut state = self.inner.lock().unwrap();
+            if state.interval != interval {
+                tracing::info!(
+                    "Adapting request interval to {}ms due to rate limit policy {}",
+                    interval.as_millis(),
+                    policy.name
+                );
+                state.interval = interval;
+            }
+        }
+
+        if let Some(restriction) = policy.active_restriction() {
+            self.restrict(restriction);
+        }
+    }
+
+    /// Holds back all requests for the given duration
+    pub(crate) fn restrict(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut state = self.inner.lock().unwrap();
+        state.restricted_until = Some(state.restricted_until.map_or(until, |u| u.max(until)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::{parse_retry_after, RateLimitPolicy, RateLimitRule, RateLimitRuleState};
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        let headers = headers(&[
+            ("x-rate-limit-policy", "public-stash-tabs-request-limit"),
+            ("x-rate-limit-rules", "Ip"),
+            ("x-rate-limit-ip", "45:60:60,240:240:900"),
+            ("x-rate-limit-ip-state", "1:60:0,46:240:120"),
+        ]);
+
+        let policy = RateLimitPolicy::from_headers(&headers).unwrap();
+
+        assert_eq!(policy.name, "public-stash-tabs-request-limit");
+        assert_eq!(
+            policy.rules[0],
+            (
+                RateLimitRule {
+                    max_hits: 45,
+                    period: Duration::from_secs(60),
+                    restriction: Duration::from_secs(60),
+                },
+                Some(RateLimitRuleState {
+                    hits: 1,
+                    period: Duration::from_secs(60),
+                    active_restriction: Duration::ZERO,
+                })
+            )
+        );
+        assert_eq!(
+            policy.request_interval(),
+            Some(Duration::from_secs(60) / 45)
+        );
+        assert_eq!(policy.active_restriction(), Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn test_parse_policy_without_headers() {
+        assert_eq!(RateLimitPolicy::from_headers(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(
+            parse_retry_after(&headers(&[("retry-after", "12")])),
+            Some(Duration::from_secs(12))
+        );
+        assert_eq!(
+            parse_retry_after(&headers(&[("retry-after", "soon")])),
+            None
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use chrono::NaiveDateTime;
 use trade_common::secret::SecretString;
 
 #[derive(Debug)]
@@ -6,6 +7,7 @@ pub struct Configuration {
     pub client_id: String,
     pub client_secret: SecretString,
     pub developer_mail: SecretString,
+    pub replay: Option<ReplayConfig>,
 }
 
 impl Configuration {
@@ -15,6 +17,7 @@ impl Configuration {
             client_id: ensure_string_from_env("POE_CLIENT_ID"),
             client_secret: SecretString::new(ensure_string_from_env("POE_CLIENT_SECRET")),
             developer_mail: SecretString::new(ensure_string_from_env("POE_DEVELOPER_MAIL")),
+            replay: ReplayConfig::from_env()?,
         })
     }
 }
@@ -59,3 +62,40 @@ impl S3Config {
         }
     }
 }
+
+/// Replays archived stashes instead of following the live API
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    pub dir: String,
+    pub from: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+    pub leagues: Option<Vec<String>>,
+    pub speed: Option<f64>,
+}
+
+impl ReplayConfig {
+    pub fn from_env() -> Result<Option<ReplayConfig>, std::env::VarError> {
+        let Ok(dir) = std::env::var("REPLAY_DIR") else {
+            return Ok(None);
+        };
+
+        let datetime = |name: &str| {
+            std::env::var(name).ok().map(|s| {
+                NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M")
+                    .unwrap_or_else(|_| panic!("Invalid {name}, expected YYYY-MM-DDTHH:MM"))
+            })
+        };
+
+        Ok(Some(ReplayConfig {
+            dir,
+            from: datetime("REPLAY_FROM"),
+            until: datetime("REPLAY_UNTIL"),
+            leagues: std::env::var("REPLAY_LEAGUES")
+                .ok()
+                .map(|s| s.split(',').map(|l| l.trim().to_string()).collect()),
+            speed: std::env::var("REPLAY_SPEED")
+                .ok()
+                .map(|s| s.parse().expect("Invalid REPLAY_SPEED")),
+        }))
+    }
+}
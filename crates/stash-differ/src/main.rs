@@ -5,15 +5,21 @@ mod differ;
 mod s3;
 mod store;
 
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
 use crate::{config::Configuration, s3::S3Sink};
 use stash_api::{
     common::poe_ninja_client::PoeNinjaClient,
-    r#async::indexer::{Indexer, IndexerMessage},
+    r#async::{
+        indexer::{Indexer, IndexerMessage},
+        replayer::Replayer,
+    },
 };
 use tracing::info;
 use trade_common::telemetry::setup_telemetry;
@@ -39,26 +45,69 @@ async fn main() -> Result<()> {
     let client_secret = config.client_secret.clone();
     let developer_mail = config.developer_mail.clone();
 
-    let indexer = Indexer::new(client_id, client_secret, developer_mail);
-
-    let latest_change_id = PoeNinjaClient::fetch_latest_change_id_async()
-        .await
-        .unwrap();
-    let mut rx = indexer.start_at_change_id(latest_change_id).await;
+    let (handle, mut rx) = match config.replay {
+        Some(replay) => {
+            tracing::info!("Replaying archived stashes from {}", replay.dir);
+            let mut replayer = Replayer::from_dir(replay.dir).time_range(replay.from, replay.until);
+            if let Some(leagues) = replay.leagues {
+                replayer = replayer.leagues(leagues);
+            }
+            if let Some(speed) = replay.speed {
+                replayer = replayer.speed(speed);
+            }
+            (
+                None,
+                replayer.start().await.map_err(|e| anyhow::anyhow!(e))?,
+            )
+        }
+        None => {
+            let indexer = Indexer::new(client_id, client_secret, developer_mail);
+            let latest_change_id = PoeNinjaClient::fetch_latest_change_id_async()
+                .await
+                .unwrap();
+            let (handle, rx) = indexer.start_at_change_id(latest_change_id).await?;
+            (Some(handle), rx)
+        }
+    };
 
     let mut store = store::StashStore::new();
 
-    while let Some(msg) = rx.recv().await {
-        if signal_flag.load(Ordering::Relaxed) {
-            tracing::info!("Shutdown signal detected. Shutting down gracefully.");
+    let mut stopping = false;
+    let mut signal_check = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        let msg = tokio::select! {
+            msg = rx.recv() => msg,
+            _ = signal_check.tick(), if !stopping => {
+                if signal_flag.load(Ordering::Relaxed) {
+                    tracing::info!("Shutdown signal detected. Shutting down gracefully.");
+                    match &handle {
+                        Some(handle) => handle.stop(),
+                        None => break,
+                    }
+                    stopping = true;
+                }
+                continue;
+            }
+        };
+        let Some(msg) = msg else {
             break;
-        }
+        };
 
         match msg {
             IndexerMessage::Stop => break,
             IndexerMessage::RateLimited(timer) => {
                 tracing::info!("Rate limited for {} seconds...waiting", timer.as_secs());
             }
+            IndexerMessage::Error(e) if e.is_fatal() => {
+                tracing::error!("Shutting down due to fatal indexer error: {}", e);
+                if let Some(handle) = &handle {
+                    handle.stop();
+                }
+                break;
+            }
+            IndexerMessage::Error(e) => {
+                tracing::warn!("Indexer error: {}", e);
+            }
             IndexerMessage::Tick {
                 change_id, stashes, ..
             } => {